@@ -5,7 +5,11 @@ use std::{
 };
 use symbolic_expressions::Sexp;
 
-use crate::into_ast::{LIST_KEYWORD, RECORD_KEYWORD};
+use crate::into_ast::{
+    CAST_KEYWORD, CONST_KEYWORD, DICT_KEYWORD, DO_KEYWORD, EXPORT_KEYWORD, HOLE_KEYWORD,
+    IMPORT_KEYWORD, LIST_KEYWORD, MODULE_KEYWORD, NAMED_HOLE_PREFIX, NEWTYPE_KEYWORD,
+    OPAQUE_KEYWORD, RECORD_KEYWORD, THE_KEYWORD, TYPE_KEYWORD,
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Parameter {
@@ -86,11 +90,29 @@ pub struct Let {
     pub name: String,
     pub typ: Option<Sexp>,
     pub value: Box<Expr>,
+    /// literal types of an unannotated value are widened: `(let x 1)` is `int`.
+    /// `(let x : const 1)` keeps them: `1`
+    pub widen: bool,
 }
 
 impl Let {
     pub fn new(name: String, typ: Option<Sexp>, value: Box<Expr>) -> Self {
-        Self { name, typ, value }
+        Self {
+            name,
+            typ,
+            value,
+            widen: true,
+        }
+    }
+
+    /// `(let x : const 1)`
+    pub fn new_const(name: String, value: Box<Expr>) -> Self {
+        Self {
+            name,
+            typ: None,
+            value,
+            widen: false,
+        }
     }
 }
 
@@ -102,6 +124,8 @@ impl Display for Let {
             self.name,
             if let Some(t) = self.typ.as_ref() {
                 format!(":{}", t)
+            } else if !self.widen {
+                format!(":{}", CONST_KEYWORD)
             } else {
                 "".to_string()
             },
@@ -110,33 +134,101 @@ impl Display for Let {
     }
 }
 
+/// (let ((x 1) (y : int 2)) body)
+#[derive(Debug, Clone, PartialEq)]
+pub struct LetIn {
+    pub bindings: Vec<Let>,
+    pub body: Box<Expr>,
+}
+
+impl LetIn {
+    pub fn new(bindings: Vec<Let>, body: Box<Expr>) -> Self {
+        Self { bindings, body }
+    }
+}
+
+impl Display for LetIn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} in {}",
+            self.bindings
+                .iter()
+                .map(|b| format!("{}", b))
+                .collect::<Vec<String>>()
+                .join("; "),
+            self.body
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypeDefKind {
+    /// `(type a : int)`: structural alias
+    Alias,
+    /// `(newtype a : int)`: distinct nominal type wrapping `int`
+    NewType,
+    /// `(opaque a : int)`: nominal type whose representation `int` is visible only in the defining module
+    Opaque,
+}
+
+impl Display for TypeDefKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeDefKind::Alias => write!(f, "{}", TYPE_KEYWORD),
+            TypeDefKind::NewType => write!(f, "{}", NEWTYPE_KEYWORD),
+            TypeDefKind::Opaque => write!(f, "{}", OPAQUE_KEYWORD),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct TypeDef {
     pub name: String,
     pub typ: Sexp,
+    pub kind: TypeDefKind,
 }
 
 impl TypeDef {
-    pub fn new(name: String, typ: Sexp) -> Self {
-        Self { name, typ }
+    pub fn new(name: String, typ: Sexp, kind: TypeDefKind) -> Self {
+        Self { name, typ, kind }
+    }
+
+    /// name of the generated function wrapping a value into a newtype
+    pub fn wrap_fn(&self) -> String {
+        format!("wrap-{}", self.name)
+    }
+
+    /// name of the generated function unwrapping a newtype into its representation
+    pub fn unwrap_fn(&self) -> String {
+        format!("unwrap-{}", self.name)
     }
 }
 
 impl Display for TypeDef {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "type {} = {}", self.name, self.typ)
+        write!(f, "{} {} = {}", self.kind, self.name, self.typ)
     }
 }
 
+/// integer values. arbitrary precision with the `bigint` feature
+#[cfg(not(feature = "bigint"))]
+pub type Int = i64;
+#[cfg(feature = "bigint")]
+pub type Int = num_bigint::BigInt;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     External(String),
     Bool(bool),
-    Number(i64),
+    Number(Int),
+    Float(f64),
     Atom(String),
     String(String),
     Record(HashMap<String, Expr>),
     List(Vec<Expr>),
+    /// entries in insertion order. keys are unique
+    Dict(Vec<(Expr, Expr)>),
 }
 
 impl Value {
@@ -147,13 +239,21 @@ impl Value {
         }
     }
 
-    pub fn number(&self) -> Result<i64> {
+    #[allow(clippy::clone_on_copy)] // `Int` is `i64` without the `bigint` feature
+    pub fn number(&self) -> Result<Int> {
         match self {
-            Value::Number(n) => Ok(*n),
+            Value::Number(n) => Ok(n.clone()),
             _ => Err(anyhow::anyhow!("not number")),
         }
     }
 
+    pub fn float(&self) -> Result<f64> {
+        match self {
+            Value::Float(f) => Ok(*f),
+            _ => Err(anyhow::anyhow!("not float")),
+        }
+    }
+
     pub fn string(&self) -> Result<String> {
         match self {
             Value::String(s) => Ok(s.clone()),
@@ -181,6 +281,25 @@ impl Value {
             _ => Err(anyhow::anyhow!("not list")),
         }
     }
+
+    pub fn dict(&self) -> Result<&Vec<(Expr, Expr)>> {
+        match self {
+            Value::Dict(dict) => Ok(dict),
+            _ => Err(anyhow::anyhow!("not dict")),
+        }
+    }
+}
+
+/// source form of the float `v`, also the name of its literal type. unlike `{:?}`, which writes
+/// `1e-5` and `1e20`, it is never in exponent notation and always has a decimal point:
+/// `0.00001`, `100000000000000000000.0`
+pub fn float_literal(v: f64) -> String {
+    let name = v.to_string();
+    if name.contains('.') || !v.is_finite() {
+        name
+    } else {
+        format!("{}.0", name)
+    }
 }
 
 impl Display for Value {
@@ -189,6 +308,7 @@ impl Display for Value {
             Value::External(name) => write!(f, "(external {})", name),
             Value::Bool(b) => write!(f, "{}", b),
             Value::Number(n) => write!(f, "{}", n),
+            Value::Float(n) => write!(f, "{}", float_literal(*n)),
             Value::String(s) => write!(f, "'{}'", s),
             Value::Atom(atom) => write!(f, ":{}", atom),
             Value::Record(record) => write!(
@@ -210,6 +330,15 @@ impl Display for Value {
                     .collect::<Vec<String>>()
                     .join(" ")
             ),
+            Value::Dict(dict) => write!(
+                f,
+                "({} {})",
+                DICT_KEYWORD,
+                dict.iter()
+                    .map(|(k, v)| format!("({} : {})", k, v))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            ),
         }
     }
 }
@@ -240,6 +369,166 @@ impl Display for Case {
     }
 }
 
+/// runtime check of `expr` against `typ`, inserted by the type checker where a `dyn` value
+/// is used as another type. `location` is blamed when the check fails
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blame {
+    pub expr: Box<Expr>,
+    pub typ: Sexp,
+    pub location: String,
+    /// the module the check was inserted in. only it sees the representation of its opaque types
+    pub module: Option<String>,
+}
+
+impl Blame {
+    pub fn new(expr: Box<Expr>, typ: Sexp, location: String, module: Option<String>) -> Self {
+        Self {
+            expr,
+            typ,
+            location,
+            module,
+        }
+    }
+}
+
+impl Display for Blame {
+    /// printed as the `the` form checking the same type
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({} {} {})", THE_KEYWORD, self.typ, self.expr)
+    }
+}
+
+/// `(the int expr)`: `expr` is checked to be `int` statically, or at runtime if it is `dyn`
+#[derive(Debug, Clone, PartialEq)]
+pub struct The {
+    pub typ: Sexp,
+    pub expr: Box<Expr>,
+}
+
+impl The {
+    pub fn new(typ: Sexp, expr: Box<Expr>) -> Self {
+        Self { typ, expr }
+    }
+}
+
+impl Display for The {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({} {} {})", THE_KEYWORD, self.typ, self.expr)
+    }
+}
+
+/// `(cast int expr)`: the value of `expr` if it is `int` at runtime, otherwise `:invalid-cast`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cast {
+    pub typ: Sexp,
+    pub expr: Box<Expr>,
+    /// the module the cast is type checked in, like [Blame::module]
+    pub module: Option<String>,
+}
+
+impl Cast {
+    /// the atom returned when the value is not of the type
+    pub const INVALID: &'static str = "invalid-cast";
+
+    pub fn new(typ: Sexp, expr: Box<Expr>) -> Self {
+        Self {
+            typ,
+            expr,
+            module: None,
+        }
+    }
+}
+
+impl Display for Cast {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({} {} {})", CAST_KEYWORD, self.typ, self.expr)
+    }
+}
+
+/// names brought into scope unqualified by an import.
+/// exported names are always accessible qualified as `module.name`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportNames {
+    /// `(import std/list)`
+    Qualified,
+    /// `(import std/list (map filter))`
+    Only(Vec<String>),
+    /// `(import std/list *)`
+    All,
+}
+
+/// (import std/list (map filter))
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub path: String,
+    pub names: ImportNames,
+}
+
+impl Import {
+    pub fn new(path: String, names: ImportNames) -> Self {
+        Self { path, names }
+    }
+}
+
+impl Display for Import {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.names {
+            ImportNames::Qualified => write!(f, "({} {})", IMPORT_KEYWORD, self.path),
+            ImportNames::Only(names) => {
+                write!(
+                    f,
+                    "({} {} ({}))",
+                    IMPORT_KEYWORD,
+                    self.path,
+                    names.join(" ")
+                )
+            }
+            ImportNames::All => write!(f, "({} {} *)", IMPORT_KEYWORD, self.path),
+        }
+    }
+}
+
+/// (module name (export a b) body...)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub name: String,
+    pub body: Vec<Expr>,
+}
+
+impl Module {
+    pub fn new(name: String, body: Vec<Expr>) -> Self {
+        Self { name, body }
+    }
+
+    /// names listed in `(export ...)` forms of the body
+    pub fn exports(&self) -> Vec<String> {
+        self.body
+            .iter()
+            .filter_map(|e| match e {
+                Expr::Export(names) => Some(names.clone()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+}
+
+impl Display for Module {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "({} {}\n  {}\n)",
+            MODULE_KEYWORD,
+            self.name,
+            self.body
+                .iter()
+                .map(|e| format!("{}", e))
+                .collect::<Vec<String>>()
+                .join("\n  ")
+        )
+    }
+}
+
 pub fn from_expr(expr: &Expr) -> Result<Value> {
     match expr {
         Expr::Literal(v) => Ok(v.clone()),
@@ -252,11 +541,24 @@ pub enum Expr {
     Literal(Value),
     Variable(String),
     Let(Let),
+    LetIn(LetIn),
+    /// (do e1 e2 ... en)
+    Do(Vec<Expr>),
     FnApp(FnApp),
     FnDef(FnDef),
     TypeDef(TypeDef),
     Case(Case),
-    Include(String),
+    Module(Module),
+    /// (export a b)
+    Export(Vec<String>),
+    Import(Import),
+    /// `_` or `?name`. the type checker reports the type expected at the hole
+    Hole(Option<String>),
+    Blame(Blame),
+    /// (the int expr)
+    The(The),
+    /// (cast int expr)
+    Cast(Cast),
 }
 
 impl Expr {
@@ -275,7 +577,7 @@ impl Expr {
     }
 
     pub fn has_context(&self) -> bool {
-        matches!(self, Expr::Let(_) | Expr::FnDef(_))
+        matches!(self, Expr::Let(_) | Expr::LetIn(_) | Expr::FnDef(_))
     }
 }
 
@@ -285,14 +587,57 @@ impl Display for Expr {
             Expr::Literal(literal) => write!(f, "{}", literal),
             Expr::Variable(name) => write!(f, "{}", name),
             Expr::Let(let_) => write!(f, "{}", let_),
+            Expr::LetIn(let_in) => write!(f, "{}", let_in),
+            Expr::Do(exprs) => write!(
+                f,
+                "({} {})",
+                DO_KEYWORD,
+                exprs
+                    .iter()
+                    .map(|e| format!("{}", e))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            ),
             Expr::FnApp(fn_app) => write!(f, "{}", fn_app),
             Expr::FnDef(fn_def) => write!(f, "{}", fn_def),
             Expr::TypeDef(type_def) => write!(f, "{}", type_def),
             Expr::Case(case) => write!(f, "{}", case),
-            Expr::Include(file) => write!(f, "(include \"{}\")", file),
+            Expr::Module(module) => write!(f, "{}", module),
+            Expr::Export(names) => write!(f, "({} {})", EXPORT_KEYWORD, names.join(" ")),
+            Expr::Import(import) => write!(f, "{}", import),
+            Expr::Hole(None) => write!(f, "{}", HOLE_KEYWORD),
+            Expr::Hole(Some(name)) => write!(f, "{}{}", NAMED_HOLE_PREFIX, name),
+            Expr::Blame(blame) => write!(f, "{}", blame),
+            Expr::The(the) => write!(f, "{}", the),
+            Expr::Cast(cast) => write!(f, "{}", cast),
         }
     }
 }
 
 #[derive(Debug)]
 pub struct Program(pub Vec<Expr>);
+
+impl Program {
+    /// the `(module ...)` form of the program
+    pub fn module(&self) -> Option<&Module> {
+        self.0.iter().find_map(|e| match e {
+            Expr::Module(module) => Some(module),
+            _ => None,
+        })
+    }
+
+    /// imports at the top level and in the body of modules, which are loaded before the program
+    pub fn imports_mut(&mut self) -> Vec<&mut Import> {
+        self.0
+            .iter_mut()
+            .flat_map(|e| match e {
+                Expr::Module(module) => module.body.iter_mut().collect(),
+                e => vec![e],
+            })
+            .filter_map(|e| match e {
+                Expr::Import(import) => Some(import),
+                _ => None,
+            })
+            .collect()
+    }
+}
@@ -1,16 +1,34 @@
-use crate::ast::{Case, Expr, FnApp, FnDef, Let, Parameter, TypeDef, Value};
+use crate::ast::{
+    Case, Cast, Expr, FnApp, FnDef, Import, ImportNames, Let, LetIn, Module, Parameter, The,
+    TypeDef, TypeDefKind, Value,
+};
 use anyhow::Result;
 use std::collections::HashMap;
 use symbolic_expressions::Sexp;
 
 pub const LET_KEYWORD: &str = "let";
+/// annotation of `let` keeping literal types: `(let x : const 1)`
+pub const CONST_KEYWORD: &str = "const";
+pub const DO_KEYWORD: &str = "do";
 pub const FN_KEYWORD: &str = "fn";
 pub const RECORD_KEYWORD: &str = "record";
 pub const LIST_KEYWORD: &str = "vec";
+pub const DICT_KEYWORD: &str = "dict";
 pub const TYPE_KEYWORD: &str = "type";
+pub const NEWTYPE_KEYWORD: &str = "newtype";
+pub const OPAQUE_KEYWORD: &str = "opaque";
 pub const CASE_KEYWORD: &str = "case";
 pub const EXTERNAL_KEYWORD: &str = "external";
 pub const INCLUDE_KEYWORD: &str = "include";
+pub const MODULE_KEYWORD: &str = "module";
+pub const EXPORT_KEYWORD: &str = "export";
+pub const IMPORT_KEYWORD: &str = "import";
+/// typed hole: `_`, or named `?name`
+pub const HOLE_KEYWORD: &str = "_";
+pub const NAMED_HOLE_PREFIX: char = '?';
+/// `(the int expr)` and `(cast int expr)`
+pub const THE_KEYWORD: &str = "the";
+pub const CAST_KEYWORD: &str = "cast";
 
 fn parse_parameter(sexp: &Sexp) -> Result<Parameter> {
     match sexp {
@@ -39,9 +57,50 @@ fn parse_fn(list: &[Sexp]) -> Result<Expr> {
     Ok(Expr::FnDef(FnDef::new(params, body)))
 }
 
+/// `(let x : typ value)`. `const` is not a type but keeps literal types of `value`
+fn annotated_let(name: &Sexp, typ: &Sexp, value: &Sexp) -> Result<Let> {
+    let name = name.string()?.to_string();
+    let value = Box::new(into_ast(value)?);
+    Ok(match typ {
+        Sexp::String(s) if s == CONST_KEYWORD => Let::new_const(name, value),
+        typ => Let::new(name, Some(typ.clone()), value),
+    })
+}
+
+/// `(x 1)`, `(x : int 1)` or `(x : const 1)` in let block
+fn parse_binding(sexp: &Sexp) -> Result<Let> {
+    let list = sexp.list()?;
+    match list.len() {
+        2 => Ok(Let::new(
+            list[0].string()?.to_string(),
+            None,
+            Box::new(into_ast(&list[1])?),
+        )),
+        4 if list[1].string().ok() == Some(&":".to_string()) => {
+            annotated_let(&list[0], &list[2], &list[3])
+        }
+        _ => Err(anyhow::anyhow!(
+            "binding must be (x value) or (x : type value). but {}",
+            sexp
+        )),
+    }
+}
+
 fn parse_let(sexp: &Sexp) -> Result<Expr> {
     let list = sexp.list()?;
     match list.len() {
+        // let block: `(let ((a 1) (b 2)) body)`
+        3 if list[1].is_list() => {
+            let bindings = list[1]
+                .list()?
+                .iter()
+                .map(parse_binding)
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Expr::LetIn(LetIn::new(
+                bindings,
+                Box::new(into_ast(&list[2])?),
+            )))
+        }
         // without type annotation: `(let a 1)`
         3 => Ok(Expr::Let(Let::new(
             list[1].string()?.to_string(),
@@ -49,11 +108,9 @@ fn parse_let(sexp: &Sexp) -> Result<Expr> {
             Box::new(into_ast(&list[2])?),
         ))),
         // with type annotation: `(let a : int 1)`
-        5 if list[2].string().ok() == Some(&":".to_string()) => Ok(Expr::Let(Let::new(
-            list[1].string()?.to_string(),
-            Some(list[3].clone()),
-            Box::new(into_ast(&list[4])?),
-        ))),
+        5 if list[2].string().ok() == Some(&":".to_string()) => {
+            Ok(Expr::Let(annotated_let(&list[1], &list[3], &list[4])?))
+        }
         _ => Err(anyhow::anyhow!(
             "let must have 2 or 3 operands. but {}",
             sexp
@@ -61,7 +118,8 @@ fn parse_let(sexp: &Sexp) -> Result<Expr> {
     }
 }
 
-fn parse_type(sexp: &Sexp) -> Result<Expr> {
+/// (type a : int), (newtype a : int), (opaque a : int)
+fn parse_type(sexp: &Sexp, kind: TypeDefKind) -> Result<Expr> {
     let list = sexp.list()?;
     anyhow::ensure!(
         list[2].is_string() && list[2].string()? == ":",
@@ -69,7 +127,7 @@ fn parse_type(sexp: &Sexp) -> Result<Expr> {
         sexp
     );
     let (name, typ) = (list[1].string()?, list[3].clone());
-    Ok(Expr::TypeDef(TypeDef::new(name.to_string(), typ)))
+    Ok(Expr::TypeDef(TypeDef::new(name.to_string(), typ, kind)))
 }
 
 /// (f g h) -> ((f g) h)
@@ -96,8 +154,38 @@ fn parse_list(elements: &[Sexp]) -> Result<Value> {
     Ok(Value::List(elements))
 }
 
+/// (dict ('a' : 1) (:b : 2)). keys are expressions
+fn parse_dict(entries: &[Sexp]) -> Result<Value> {
+    let entries = entries
+        .iter()
+        .map(|entry| {
+            let entry = entry.list()?;
+            anyhow::ensure!(
+                entry.len() == 3 && entry[1].is_string() && entry[1].string()? == ":",
+                "dict entry must be (key : value). but {:?}",
+                entry
+            );
+            Ok((into_ast(&entry[0])?, into_ast(&entry[2])?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Value::Dict(entries))
+}
+
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
+}
+
+/// `3`, `-3`
 fn is_number(s: &str) -> bool {
-    s.chars().all(|c| c.is_numeric())
+    is_digits(s.strip_prefix('-').unwrap_or(s))
+}
+
+/// `1.5`, `-0.5`. both the integer and fractional parts are required
+fn is_float(s: &str) -> bool {
+    s.strip_prefix('-')
+        .unwrap_or(s)
+        .split_once('.')
+        .is_some_and(|(int, frac)| is_digits(int) && is_digits(frac))
 }
 
 pub fn parse_case(branches: &[Sexp]) -> Result<Expr> {
@@ -117,17 +205,92 @@ pub fn parse_case(branches: &[Sexp]) -> Result<Expr> {
     Ok(Expr::Case(Case::new(branches)))
 }
 
+/// (import std/list), (import std/list (map filter)), (import std/list *)
+fn parse_import(list: &[Sexp]) -> Result<Expr> {
+    let path = import_path(list)?;
+    let names = match list.get(2) {
+        None => ImportNames::Qualified,
+        Some(Sexp::String(s)) if s == "*" => ImportNames::All,
+        Some(Sexp::List(names)) => ImportNames::Only(
+            names
+                .iter()
+                .map(|name| Ok(name.string()?.to_string()))
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        Some(names) => return Err(anyhow::anyhow!("invalid import list {}", names)),
+    };
+    Ok(Expr::Import(Import::new(path, names)))
+}
+
+/// the path of `(import path ...)` and `(include path)`
+fn import_path(list: &[Sexp]) -> Result<String> {
+    Ok(list
+        .get(1)
+        .ok_or(anyhow::anyhow!("import path is required"))?
+        .string()?
+        .to_string())
+}
+
+/// (module name body...)
+fn parse_module(list: &[Sexp]) -> Result<Expr> {
+    let name = list
+        .get(1)
+        .ok_or(anyhow::anyhow!("module name is required"))?
+        .string()?
+        .to_string();
+    let body = list[2..].iter().map(into_ast).collect::<Result<Vec<_>>>()?;
+    Ok(Expr::Module(Module::new(name, body)))
+}
+
+/// `(the typ expr)` or `(cast typ expr)`
+fn parse_annotated(sexp: &Sexp) -> Result<(Sexp, Box<Expr>)> {
+    match sexp.list()?.as_slice() {
+        [_, typ, expr] => Ok((typ.clone(), Box::new(into_ast(expr)?))),
+        _ => Err(anyhow::anyhow!(
+            "must be (the type expr) or (cast type expr). but {}",
+            sexp
+        )),
+    }
+}
+
 pub fn into_ast(sexp: &Sexp) -> Result<Expr> {
     let _span = tracing::debug_span!("", "{}", sexp).entered();
     let expr = match sexp {
         Sexp::List(list) => match list[0] {
             Sexp::String(ref head) if head == FN_KEYWORD => parse_fn(list),
             Sexp::String(ref head) if head == LET_KEYWORD => parse_let(sexp),
-            Sexp::String(ref head) if head == TYPE_KEYWORD => parse_type(sexp),
+            Sexp::String(ref head) if head == TYPE_KEYWORD => parse_type(sexp, TypeDefKind::Alias),
+            Sexp::String(ref head) if head == NEWTYPE_KEYWORD => {
+                parse_type(sexp, TypeDefKind::NewType)
+            }
+            Sexp::String(ref head) if head == OPAQUE_KEYWORD => {
+                parse_type(sexp, TypeDefKind::Opaque)
+            }
             Sexp::String(ref head) if head == CASE_KEYWORD => parse_case(&list[1..]),
-            Sexp::String(ref head) if head == INCLUDE_KEYWORD => {
-                Ok(Expr::Include(list[1].string()?.to_string()))
+            Sexp::String(ref head) if head == DO_KEYWORD => Ok(Expr::Do(
+                list[1..].iter().map(into_ast).collect::<Result<Vec<_>>>()?,
+            )),
+            // `(include path)` imports every exported name unqualified
+            Sexp::String(ref head) if head == INCLUDE_KEYWORD => Ok(Expr::Import(Import::new(
+                import_path(list)?,
+                ImportNames::All,
+            ))),
+            Sexp::String(ref head) if head == IMPORT_KEYWORD => parse_import(list),
+            Sexp::String(ref head) if head == THE_KEYWORD => {
+                let (typ, expr) = parse_annotated(sexp)?;
+                Ok(Expr::The(The::new(typ, expr)))
+            }
+            Sexp::String(ref head) if head == CAST_KEYWORD => {
+                let (typ, expr) = parse_annotated(sexp)?;
+                Ok(Expr::Cast(Cast::new(typ, expr)))
             }
+            Sexp::String(ref head) if head == MODULE_KEYWORD => parse_module(list),
+            Sexp::String(ref head) if head == EXPORT_KEYWORD => Ok(Expr::Export(
+                list[1..]
+                    .iter()
+                    .map(|name| Ok(name.string()?.to_string()))
+                    .collect::<Result<Vec<_>>>()?,
+            )),
             _ if list[0].is_string() && list[0].string()?.as_str() == EXTERNAL_KEYWORD => Ok(
                 Expr::Literal(Value::External(list[1].string()?.to_string())),
             ),
@@ -137,10 +300,14 @@ pub fn into_ast(sexp: &Sexp) -> Result<Expr> {
             _ if list[0].is_string() && list[0].string()?.as_str() == LIST_KEYWORD => {
                 Ok(Expr::Literal(parse_list(&list[1..])?))
             }
+            _ if list[0].is_string() && list[0].string()?.as_str() == DICT_KEYWORD => {
+                Ok(Expr::Literal(parse_dict(&list[1..])?))
+            }
             _ => parse_apply(&list[0], &list[1..]),
         },
         Sexp::String(lit) => match lit.as_str() {
             _ if is_number(lit) => Ok(Expr::Literal(Value::Number(lit.parse()?))),
+            _ if is_float(lit) => Ok(Expr::Literal(Value::Float(lit.parse()?))),
             _ if lit.starts_with('\'') && lit.ends_with('\'') => Ok(Expr::Literal(Value::String(
                 lit[1..lit.len() - 1].to_string(),
             ))),
@@ -148,6 +315,10 @@ pub fn into_ast(sexp: &Sexp) -> Result<Expr> {
             _ if lit.starts_with(':') => Ok(Expr::Literal(Value::Atom(
                 lit.trim_start_matches(':').to_string(),
             ))),
+            HOLE_KEYWORD => Ok(Expr::Hole(None)),
+            _ if lit.len() > 1 && lit.starts_with(NAMED_HOLE_PREFIX) => Ok(Expr::Hole(Some(
+                lit.trim_start_matches(NAMED_HOLE_PREFIX).to_string(),
+            ))),
             _ => Ok(Expr::Variable(lit.to_string())),
         },
         _ => Err(anyhow::anyhow!("invalid sexp: {}", sexp)),
@@ -159,7 +330,10 @@ pub fn into_ast(sexp: &Sexp) -> Result<Expr> {
 #[cfg(test)]
 mod tests {
     use super::{into_ast, parse_parameter};
-    use crate::ast::{Expr, FnApp, FnDef, Let, Parameter, TypeDef, Value};
+    use crate::ast::{
+        Cast, Expr, FnApp, FnDef, Import, ImportNames, Let, LetIn, Module, Parameter, The, TypeDef,
+        TypeDefKind, Value,
+    };
     use anyhow::Result;
     use std::collections::HashMap;
     use symbolic_expressions::{parser::parse_str, Sexp};
@@ -173,7 +347,26 @@ mod tests {
 
     #[test]
     fn int_literal() -> Result<()> {
-        should_be_ast("1", &Expr::Literal(Value::Number(1)))
+        should_be_ast("1", &Expr::Literal(Value::Number(1.into())))
+    }
+
+    #[test]
+    fn negative_int_literal() -> Result<()> {
+        should_be_ast("-3", &Expr::Literal(Value::Number((-3).into())))?;
+        should_be_ast("-", &Expr::Variable("-".to_string()))
+    }
+
+    #[test]
+    fn float_literal() -> Result<()> {
+        should_be_ast("1.5", &Expr::Literal(Value::Float(1.5)))?;
+        should_be_ast("-0.25", &Expr::Literal(Value::Float(-0.25)))?;
+        should_be_ast("1.", &Expr::Variable("1.".to_string()))?;
+        // printed floats are parsed back, however small or large
+        for v in [1.0, 0.00001, -0.00001, 1e20] {
+            let value = Value::Float(v);
+            should_be_ast(&value.to_string(), &Expr::Literal(value))?;
+        }
+        Ok(())
     }
 
     #[test]
@@ -196,8 +389,8 @@ mod tests {
         should_be_ast(
             "(record (a : 1) (b : 2))",
             &Expr::Literal(Value::Record(HashMap::from_iter(vec![
-                ("a".to_string(), Expr::Literal(Value::Number(1))),
-                ("b".to_string(), Expr::Literal(Value::Number(2))),
+                ("a".to_string(), Expr::Literal(Value::Number(1.into()))),
+                ("b".to_string(), Expr::Literal(Value::Number(2.into()))),
             ]))),
         )
     }
@@ -207,9 +400,26 @@ mod tests {
         should_be_ast(
             "(vec 1 2 3)",
             &Expr::Literal(Value::List(vec![
-                Expr::Literal(Value::Number(1)),
-                Expr::Literal(Value::Number(2)),
-                Expr::Literal(Value::Number(3)),
+                Expr::Literal(Value::Number(1.into())),
+                Expr::Literal(Value::Number(2.into())),
+                Expr::Literal(Value::Number(3.into())),
+            ])),
+        )
+    }
+
+    #[test]
+    fn dict_literal() -> Result<()> {
+        should_be_ast(
+            "(dict ('a' : 1) (:b : 2))",
+            &Expr::Literal(Value::Dict(vec![
+                (
+                    Expr::Literal(Value::String("a".to_string())),
+                    Expr::Literal(Value::Number(1.into())),
+                ),
+                (
+                    Expr::Literal(Value::Atom("b".to_string())),
+                    Expr::Literal(Value::Number(2.into())),
+                ),
             ])),
         )
     }
@@ -219,6 +429,33 @@ mod tests {
         should_be_ast("x", &Expr::Variable("x".to_string()))
     }
 
+    #[test]
+    fn the_cast() -> Result<()> {
+        should_be_ast(
+            "(the int x)",
+            &Expr::The(The::new(
+                Sexp::String("int".to_string()),
+                Box::new(Expr::Variable("x".to_string())),
+            )),
+        )?;
+        should_be_ast(
+            "(cast (vec int) x)",
+            &Expr::Cast(Cast::new(
+                parse_str("(vec int)")?,
+                Box::new(Expr::Variable("x".to_string())),
+            )),
+        )?;
+        assert!(into_ast(&parse_str("(the int)")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn hole() -> Result<()> {
+        should_be_ast("_", &Expr::Hole(None))?;
+        should_be_ast("?acc", &Expr::Hole(Some("acc".to_string())))?;
+        should_be_ast("?", &Expr::Variable("?".to_string()))
+    }
+
     #[test]
     fn parameter() -> Result<()> {
         let param = parse_parameter(&parse_str("(a : int)")?)?;
@@ -238,7 +475,7 @@ mod tests {
             &Expr::Let(Let::new(
                 "x".to_string(),
                 Some(Sexp::String("int".to_string())),
-                Box::new(Expr::Literal(Value::Number(1))),
+                Box::new(Expr::Literal(Value::Number(1.into()))),
             )),
         )
     }
@@ -250,7 +487,102 @@ mod tests {
             &Expr::Let(Let::new(
                 "x".to_string(),
                 None,
-                Box::new(Expr::Literal(Value::Number(1))),
+                Box::new(Expr::Literal(Value::Number(1.into()))),
+            )),
+        )
+    }
+
+    #[test]
+    fn let_const() -> Result<()> {
+        should_be_ast(
+            "(let x : const 1)",
+            &Expr::Let(Let::new_const(
+                "x".to_string(),
+                Box::new(Expr::Literal(Value::Number(1.into()))),
+            )),
+        )?;
+        should_be_ast(
+            "(let ((x : const 1)) x)",
+            &Expr::LetIn(LetIn::new(
+                vec![Let::new_const(
+                    "x".to_string(),
+                    Box::new(Expr::Literal(Value::Number(1.into()))),
+                )],
+                Box::new(Expr::Variable("x".to_string())),
+            )),
+        )
+    }
+
+    #[test]
+    fn let_in() -> Result<()> {
+        should_be_ast(
+            "(let ((x 1) (y : int 2)) x)",
+            &Expr::LetIn(LetIn::new(
+                vec![
+                    Let::new(
+                        "x".to_string(),
+                        None,
+                        Box::new(Expr::Literal(Value::Number(1.into()))),
+                    ),
+                    Let::new(
+                        "y".to_string(),
+                        Some(Sexp::String("int".to_string())),
+                        Box::new(Expr::Literal(Value::Number(2.into()))),
+                    ),
+                ],
+                Box::new(Expr::Variable("x".to_string())),
+            )),
+        )
+    }
+
+    #[test]
+    fn do_expr() -> Result<()> {
+        should_be_ast(
+            "(do 1 x)",
+            &Expr::Do(vec![
+                Expr::Literal(Value::Number(1.into())),
+                Expr::Variable("x".to_string()),
+            ]),
+        )
+    }
+
+    #[test]
+    fn import() -> Result<()> {
+        should_be_ast(
+            "(import std/list (map filter))",
+            &Expr::Import(Import::new(
+                "std/list".to_string(),
+                ImportNames::Only(vec!["map".to_string(), "filter".to_string()]),
+            )),
+        )?;
+        should_be_ast(
+            "(include std/prelude.sexp)",
+            &Expr::Import(Import::new(
+                "std/prelude.sexp".to_string(),
+                ImportNames::All,
+            )),
+        )?;
+        for src in ["(import)", "(include)"] {
+            let err = into_ast(&parse_str(src)?).err().map(|e| e.to_string());
+            assert_eq!(err, Some("import path is required".to_string()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn module() -> Result<()> {
+        should_be_ast(
+            "(module m (export x) (let x 1))",
+            &Expr::Module(Module::new(
+                "m".to_string(),
+                vec![
+                    Expr::Export(vec!["x".to_string()]),
+                    Expr::Let(Let::new(
+                        "x".to_string(),
+                        None,
+                        Box::new(Expr::Literal(Value::Number(1.into()))),
+                    )),
+                ],
             )),
         )
     }
@@ -280,7 +612,7 @@ mod tests {
     fn app() -> Result<()> {
         let fn_app = Expr::FnApp(FnApp::new(
             Expr::Variable("succ".to_string()),
-            vec![Expr::Literal(Value::Number(1))],
+            vec![Expr::Literal(Value::Number(1.into()))],
         ));
         should_be_ast("(succ 1)", &fn_app)
     }
@@ -290,20 +622,31 @@ mod tests {
         let expr = Expr::TypeDef(TypeDef::new(
             "a".to_string(),
             Sexp::String("int".to_string()),
+            TypeDefKind::Alias,
         ));
         should_be_ast("(type a : int)", &expr)
     }
 
+    #[test]
+    fn newtype_def() -> Result<()> {
+        let expr = Expr::TypeDef(TypeDef::new(
+            "user-id".to_string(),
+            Sexp::String("int".to_string()),
+            TypeDefKind::NewType,
+        ));
+        should_be_ast("(newtype user-id : int)", &expr)
+    }
+
     #[test]
     fn case() -> Result<()> {
         let expr = Expr::Case(crate::ast::Case::new(vec![
             (
-                Expr::Literal(Value::Number(1)),
-                Expr::Literal(Value::Number(2)),
+                Expr::Literal(Value::Number(1.into())),
+                Expr::Literal(Value::Number(2.into())),
             ),
             (
-                Expr::Literal(Value::Number(3)),
-                Expr::Literal(Value::Number(4)),
+                Expr::Literal(Value::Number(3.into())),
+                Expr::Literal(Value::Number(4.into())),
             ),
         ]));
         should_be_ast("(case (1 => 2) (3 => 4))", &expr)
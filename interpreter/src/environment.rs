@@ -1,3 +1,4 @@
+use crate::loader::ModuleValues;
 use anyhow::{anyhow, Result};
 use ast::ast::Expr;
 use std::collections::HashMap;
@@ -7,6 +8,8 @@ use std::fmt::Display;
 pub struct Environment {
     pub variables: HashMap<String, Expr>,
     pub parent: Option<Box<Environment>>,
+    /// modules loaded by [crate::loader::ModuleLoader]. key is the resolved path
+    pub modules: HashMap<String, ModuleValues>,
 }
 
 impl Environment {
@@ -14,6 +17,7 @@ impl Environment {
         Self {
             variables: HashMap::new(),
             parent,
+            modules: HashMap::new(),
         }
     }
 
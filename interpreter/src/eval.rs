@@ -1,8 +1,20 @@
-use crate::{environment::Environment, externals::eval_externals, include};
+use crate::{
+    environment::Environment,
+    externals::{eval_externals, insert_entry},
+    loader::module_binding,
+};
 use anyhow::{anyhow, Ok, Result};
-use ast::ast::{Case, Expr, FnApp, FnDef, Let, Parameter, Program, Value};
-use std::{collections::HashMap, path::PathBuf};
-use structural_typesystem::{type_env::TypeEnv, types::Type};
+use ast::ast::{
+    Blame, Case, Cast, Expr, FnApp, FnDef, Import, ImportNames, Let, LetIn, Module, Parameter,
+    Program, TypeDef, TypeDefKind, Value,
+};
+use std::collections::HashMap;
+use structural_typesystem::{
+    type_check::TypeCheck,
+    type_env::TypeEnv,
+    type_eval::type_eval,
+    types::{Id, Type, DICT_TYPE_KEYWORD, LIST_TYPE_KEYWORD},
+};
 
 pub trait Eval {
     fn eval(&self, t_env: &mut TypeEnv, env: Environment) -> Result<(Expr, Environment)>;
@@ -29,6 +41,16 @@ impl Eval for Value {
                     .collect::<Result<Vec<_>>>()?;
                 Ok((Expr::Literal(Value::List(elements)), env))
             }
+            // later entries of the same key win
+            Value::Dict(entries) => {
+                let mut dict = vec![];
+                for (key, value) in entries {
+                    let (key, _) = key.eval(t_env, env.clone())?;
+                    let (value, _) = value.eval(t_env, env.clone())?;
+                    insert_entry(&mut dict, key, value);
+                }
+                Ok((Expr::Literal(Value::Dict(dict)), env))
+            }
             v => Ok((Expr::Literal(v.clone()), env)),
         }
     }
@@ -74,6 +96,142 @@ impl Eval for Let {
     }
 }
 
+impl Eval for TypeDef {
+    /// newtypes share the representation of the wrapped type, so wrap/unwrap are identity functions
+    fn eval(&self, _t_env: &mut TypeEnv, mut env: Environment) -> Result<(Expr, Environment)> {
+        if self.kind == TypeDefKind::NewType {
+            let identity = Expr::FnDef(FnDef::new(
+                vec![Parameter::new("x".to_string(), None)],
+                Box::new(Expr::Variable("x".to_string())),
+            ));
+            env.insert(&self.wrap_fn(), identity.clone());
+            env.insert(&self.unwrap_fn(), identity);
+        }
+        Ok((Expr::TypeDef(self.clone()), env))
+    }
+}
+
+impl Eval for LetIn {
+    /// (let ((a 1) (b 2)) body). functions escaping the body take the values of the bindings
+    fn eval(&self, t_env: &mut TypeEnv, env: Environment) -> Result<(Expr, Environment)> {
+        let mut inner = env.clone();
+        let mut locals = HashMap::new();
+        for binding in &self.bindings {
+            let (_, new_env) = binding.eval(t_env, inner)?;
+            inner = new_env;
+            capture(&mut locals, &inner, &binding.name)?;
+        }
+        let (res, _) = self.body.eval(t_env, inner)?;
+        Ok((substitute(&res, &locals), env))
+    }
+}
+
+/// records the value of the local binding `name` for [substitute],
+/// with the values of the preceding locals it refers to
+fn capture(locals: &mut HashMap<String, Expr>, env: &Environment, name: &str) -> Result<()> {
+    let value = substitute(env.get(name)?, locals);
+    locals.insert(name.to_string(), value);
+    Ok(())
+}
+
+/// replaces free references to `bindings` in `expr` with their values.
+/// functions have no closures, so the values of local bindings are substituted into the
+/// functions escaping their scope
+pub(crate) fn substitute(expr: &Expr, bindings: &HashMap<String, Expr>) -> Expr {
+    match expr {
+        Expr::Variable(name) => bindings.get(name).cloned().unwrap_or_else(|| expr.clone()),
+        Expr::Literal(Value::Record(fields)) => Expr::Literal(Value::Record(
+            fields
+                .iter()
+                .map(|(label, field)| (label.clone(), substitute(field, bindings)))
+                .collect(),
+        )),
+        Expr::Literal(Value::List(elems)) => Expr::Literal(Value::List(
+            elems
+                .iter()
+                .map(|elem| substitute(elem, bindings))
+                .collect(),
+        )),
+        Expr::Literal(Value::Dict(entries)) => Expr::Literal(Value::Dict(
+            entries
+                .iter()
+                .map(|(key, value)| (substitute(key, bindings), substitute(value, bindings)))
+                .collect(),
+        )),
+        Expr::Let(lt) => Expr::Let(Let {
+            value: Box::new(substitute(&lt.value, bindings)),
+            ..lt.clone()
+        }),
+        // bindings shadow the names in the following expressions
+        Expr::LetIn(let_in) => {
+            let mut bindings = bindings.clone();
+            let mut lets = vec![];
+            for binding in &let_in.bindings {
+                lets.push(Let {
+                    value: Box::new(substitute(&binding.value, &bindings)),
+                    ..binding.clone()
+                });
+                bindings.remove(&binding.name);
+            }
+            Expr::LetIn(LetIn::new(
+                lets,
+                Box::new(substitute(&let_in.body, &bindings)),
+            ))
+        }
+        Expr::Do(exprs) => {
+            let mut bindings = bindings.clone();
+            let mut substituted = vec![];
+            for expr in exprs {
+                substituted.push(substitute(expr, &bindings));
+                if let Expr::Let(lt) = expr {
+                    bindings.remove(&lt.name);
+                }
+            }
+            Expr::Do(substituted)
+        }
+        Expr::FnApp(FnApp(f, args)) => Expr::FnApp(FnApp(
+            Box::new(substitute(f, bindings)),
+            args.iter()
+                .map(|arg| Box::new(substitute(arg, bindings)))
+                .collect(),
+        )),
+        Expr::FnDef(def) => {
+            let mut bindings = bindings.clone();
+            for arg in &def.args {
+                bindings.remove(&arg.name);
+            }
+            Expr::FnDef(FnDef::new(
+                def.args.clone(),
+                Box::new(substitute(&def.body, &bindings)),
+            ))
+        }
+        Expr::Case(case) => {
+            let mut case = case.clone();
+            for (pattern, body) in &mut case.branches {
+                *pattern = substitute(pattern, bindings);
+                *body = substitute(body, bindings);
+            }
+            Expr::Case(case)
+        }
+        Expr::Blame(blame) => {
+            let mut blame = blame.clone();
+            blame.expr = Box::new(substitute(&blame.expr, bindings));
+            Expr::Blame(blame)
+        }
+        Expr::The(the) => {
+            let mut the = the.clone();
+            the.expr = Box::new(substitute(&the.expr, bindings));
+            Expr::The(the)
+        }
+        Expr::Cast(cast) => {
+            let mut cast = cast.clone();
+            cast.expr = Box::new(substitute(&cast.expr, bindings));
+            Expr::Cast(cast)
+        }
+        expr => expr.clone(),
+    }
+}
+
 impl Eval for FnApp {
     fn eval(&self, t_env: &mut TypeEnv, env: Environment) -> Result<(Expr, Environment)> {
         let (f, mut env) = self.0.eval(t_env, env)?;
@@ -108,24 +266,202 @@ impl Eval for Case {
     }
 }
 
+/// whether the runtime `value` is of type `ty` when checked in `module`.
+/// functions are only checked for their arity
+fn conforms(t_env: &mut TypeEnv, value: &Expr, ty: Id, module: Option<&str>) -> Result<bool> {
+    let ty = type_eval(t_env, ty)?;
+    if ty == t_env.primitive("any")? || t_env.is_dyn(ty)? {
+        return Ok(true);
+    }
+    let vec_ty = t_env.new_type_str(LIST_TYPE_KEYWORD)?;
+    let dict_ty = t_env.new_type_str(DICT_TYPE_KEYWORD)?;
+    match (value, t_env.alloc.get(ty)?) {
+        (_, Type::Union { types, .. }) => {
+            for member in types {
+                if conforms(t_env, value, member, module)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        // values of nominal types share the representation of the wrapped type, so only the
+        // module defining an opaque type can tell them apart. newtypes are made by their wrap fn
+        (
+            _,
+            Type::Nominal {
+                repr,
+                module: Some(defining),
+                ..
+            },
+        ) if module == Some(defining.as_str()) => conforms(t_env, value, repr, module),
+        (_, Type::Nominal { .. }) => Ok(false),
+        (Expr::FnDef(def), Type::Function { args, .. }) => Ok(def.args.len() == args.len()),
+        (Expr::Literal(Value::Record(fields)), Type::Record { fields: types, .. }) => {
+            if !fields.keys().all(|k| types.contains_key(k)) || fields.len() != types.len() {
+                return Ok(false);
+            }
+            for (name, field) in fields {
+                if !conforms(t_env, field, types[name], module)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        (Expr::Literal(Value::List(elems)), Type::Container { id, elements }) if id == vec_ty => {
+            for elem in elems {
+                if !conforms(t_env, elem, elements[0], module)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        (Expr::Literal(Value::Dict(entries)), Type::Container { id, elements })
+            if id == dict_ty =>
+        {
+            for (key, value) in entries {
+                if !conforms(t_env, key, elements[0], module)?
+                    || !conforms(t_env, value, elements[1], module)?
+                {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        // scalars have literal types
+        (
+            Expr::Literal(
+                v @ (Value::Number(_)
+                | Value::Float(_)
+                | Value::Bool(_)
+                | Value::Atom(_)
+                | Value::String(_)),
+            ),
+            _,
+        ) => {
+            let value_ty = v.clone().synth(t_env)?;
+            t_env.is_subtype(value_ty, ty)
+        }
+        _ => Ok(false),
+    }
+}
+
+impl Eval for Blame {
+    /// blames the location of the check if the value is not of the type
+    fn eval(&self, t_env: &mut TypeEnv, env: Environment) -> Result<(Expr, Environment)> {
+        let (value, env) = self.expr.eval(t_env, env)?;
+        let ty = t_env.new_type(&self.typ)?;
+        if !conforms(t_env, &value, ty, self.module.as_deref())? {
+            return Err(anyhow!(
+                "cast failed at {}: {} is not {}",
+                self.location,
+                value,
+                self.typ
+            ));
+        }
+        Ok((value, env))
+    }
+}
+
+impl Eval for Cast {
+    fn eval(&self, t_env: &mut TypeEnv, env: Environment) -> Result<(Expr, Environment)> {
+        let (value, env) = self.expr.eval(t_env, env)?;
+        let ty = t_env.new_type(&self.typ)?;
+        if conforms(t_env, &value, ty, self.module.as_deref())? {
+            Ok((value, env))
+        } else {
+            Ok((Expr::Literal(Value::Atom(Cast::INVALID.to_string())), env))
+        }
+    }
+}
+
+impl Eval for Import {
+    fn eval(&self, _t_env: &mut TypeEnv, mut env: Environment) -> Result<(Expr, Environment)> {
+        let module = env
+            .modules
+            .get(&self.path)
+            .cloned()
+            .ok_or(anyhow!("module {} is not loaded", self.path))?;
+        // functions have no closures, so the bindings of the module must be reachable
+        // from the caller's environment when an exported function is applied
+        for (name, value) in &module.bindings {
+            env.insert(&module_binding(&self.path, name), value.clone());
+        }
+        for name in &module.exports {
+            env.insert(
+                &format!("{}.{}", module.name, name),
+                module.bindings[name].clone(),
+            );
+        }
+        let names = match &self.names {
+            ImportNames::Qualified => vec![],
+            // type names are imported by the type checker
+            ImportNames::Only(names) => names
+                .iter()
+                .filter(|name| module.exports.contains(name))
+                .cloned()
+                .collect(),
+            ImportNames::All => module.exports.clone(),
+        };
+        for name in names {
+            let value = module.bindings.get(&name).ok_or(anyhow!(
+                "{} is not exported by module {}",
+                name,
+                module.name
+            ))?;
+            env.insert(&name, value.clone());
+        }
+        Ok((Expr::Literal(Value::Atom(module.name)), env))
+    }
+}
+
+impl Eval for Module {
+    fn eval(&self, t_env: &mut TypeEnv, env: Environment) -> Result<(Expr, Environment)> {
+        let mut env = env;
+        for expr in &self.body {
+            let (_, new_env) = expr.eval(t_env, env)?;
+            env = new_env;
+        }
+        Ok((Expr::Literal(Value::Atom(self.name.clone())), env))
+    }
+}
+
 impl Eval for Expr {
     fn eval(&self, t_env: &mut TypeEnv, env: Environment) -> Result<(Expr, Environment)> {
         let _span = tracing::debug_span!("", "{}", self).entered();
         let (res, env) = match self {
             Expr::FnDef(fndef) => fndef.eval(t_env, env),
             Expr::Let(r#let) => r#let.eval(t_env, env),
+            Expr::LetIn(let_in) => let_in.eval(t_env, env),
+            Expr::Do(exprs) => {
+                let mut inner = env.clone();
+                let mut locals = HashMap::new();
+                let mut last_expr = Expr::Literal(Value::Number(0.into()));
+                for expr in exprs {
+                    let (value, new_env) = expr.eval(t_env, inner)?;
+                    inner = new_env;
+                    if let Expr::Let(lt) = expr {
+                        capture(&mut locals, &inner, &lt.name)?;
+                    }
+                    last_expr = value;
+                }
+                Ok((substitute(&last_expr, &locals), env))
+            }
             Expr::FnApp(fnapp) => fnapp.eval(t_env, env),
             e @ Expr::Literal(Value::External(_)) => Ok((e.clone(), env)),
             Expr::Literal(lit) => lit.eval(t_env, env),
             Expr::Variable(var) => Ok((env.get(var)?.clone(), env)),
             Expr::Case(case) => case.eval(t_env, env),
-            Expr::Include(path) => {
-                let path = project_root::get_project_root()?.join(PathBuf::from(path));
-                log::debug!("{}", path.display());
-                let program = include(&path)?;
-                program.eval(t_env, env)
+            Expr::Module(module) => module.eval(t_env, env),
+            Expr::Export(_) => Ok((Expr::Literal(Value::Atom("export".to_string())), env)),
+            Expr::Import(import) => import.eval(t_env, env),
+            Expr::Hole(_) => Err(anyhow!("hole {} can not be evaluated", self)),
+            Expr::Blame(blame) => blame.eval(t_env, env),
+            // statically checked. `dyn` values are checked by the inserted [Blame]
+            Expr::The(the) => the.expr.eval(t_env, env),
+            Expr::Cast(cast) => cast.eval(t_env, env),
+            e @ Expr::TypeDef(type_def) => {
+                type_def.eval(t_env, env).map(|(_, env)| (e.clone(), env))
             }
-            e @ Expr::TypeDef(_) => Ok((e.clone(), env)),
         }?;
         log::debug!("= {}", res);
         Ok((res, env))
@@ -135,7 +471,7 @@ impl Eval for Expr {
 impl Eval for Program {
     fn eval(&self, t_env: &mut TypeEnv, env: Environment) -> Result<(Expr, Environment)> {
         let mut env = env;
-        let mut last_expr = Expr::Literal(Value::Number(0));
+        let mut last_expr = Expr::Literal(Value::Number(0.into()));
         for expr in &self.0 {
             let (expr, new_env) = expr.eval(t_env, env)?;
             env = new_env;
@@ -146,27 +482,30 @@ impl Eval for Program {
 }
 
 #[cfg(test)]
-mod tests {
-    use super::Eval;
-    use crate::{environment::Environment, parse, tests::setup};
+pub(crate) mod tests {
+    use crate::{loader::ModuleLoader, tests::setup};
     use anyhow::Result;
-    use ast::{ast::Expr, into_ast::into_ast};
-    use structural_typesystem::type_env::TypeEnv;
+    use ast::into_ast::into_ast;
     use symbolic_expressions::parser::parse_str;
 
-    fn should_eval(expr: &str, expected: &str) -> Result<()> {
-        let expr = parse(expr)?;
+    pub(crate) fn should_eval(expr: &str, expected: &str) -> Result<()> {
         let expected = into_ast(&parse_str(expected)?)?;
-
-        let mut type_env = TypeEnv::default();
-        let env = Environment::new(None);
-        let (_, env) = Expr::Include("std/prelude.sexp".to_string()).eval(&mut type_env, env)?;
         setup();
-        let (evaluated, _) = expr.eval(&mut type_env, env)?;
+        let evaluated =
+            ModuleLoader::new().run_str(&format!("(include std/prelude.sexp)\n{}", expr))?;
         assert_eq!(evaluated, expected);
         Ok(())
     }
 
+    #[test]
+    fn test_newtype() -> Result<()> {
+        should_eval(
+            r#"(newtype user-id : int)
+            (unwrap-user-id (wrap-user-id 3))"#,
+            "3",
+        )
+    }
+
     #[test]
     fn test_nest_fn() -> Result<()> {
         should_eval(
@@ -175,4 +514,26 @@ mod tests {
             "6",
         )
     }
+
+    #[test]
+    fn test_let_in() -> Result<()> {
+        should_eval("(let ((x 1) (y : int (+ x 1))) (+ x y))", "3")?;
+        should_eval("(do (let x 1) (let y 2) (+ x y))", "3")?;
+        should_eval(
+            r#"(let x 10)
+            (let ((x 1)) x)
+            x"#,
+            "10",
+        )
+    }
+
+    #[test]
+    fn test_escaping_fn() -> Result<()> {
+        should_eval("(let f (let ((y 1)) (fn x (+ x y)))) (f 1)", "2")?;
+        should_eval("(let f (do (let y 1) (fn x (+ x y)))) (f 1)", "2")?;
+        should_eval(
+            "(let h (let ((y 1) (g (fn x (+ x y)))) (fn x (g x)))) (h 1)",
+            "2",
+        )
+    }
 }
@@ -1,6 +1,10 @@
 use crate::{environment::Environment, eval::Eval};
 use anyhow::Result;
-use ast::ast::{Expr, FnApp, Value};
+use ast::ast::{Expr, FnApp, Int, Value};
+use num_traits::{
+    CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, FromPrimitive, ToPrimitive, Zero,
+};
+use std::collections::HashMap;
 use structural_typesystem::type_env::TypeEnv;
 
 pub fn eval_externals(
@@ -19,12 +23,74 @@ pub fn eval_externals(
         "not" => bool_not(&env, args),
         "&" => bool_and(&env, args),
         "|" => bool_or(&env, args),
+        "*" => number_mul(&env, args),
+        "/" => number_div(&env, args),
+        "<" => number_lt(&env, args),
+        "<=" => number_le(&env, args),
+        ">" => number_gt(&env, args),
+        ">=" => number_ge(&env, args),
+        "abs" => number_abs(&env, args),
+        "min" => number_min(&env, args),
+        "max" => number_max(&env, args),
+        "pow" => number_pow(&env, args),
+        "+." => float_op(&args, |a, b| a + b),
+        "-." => float_op(&args, |a, b| a - b),
+        "*." => float_op(&args, |a, b| a * b),
+        "/." => float_op(&args, |a, b| a / b),
+        "<." => float_compare(&args, f64::lt),
+        "<=." => float_compare(&args, f64::le),
+        ">." => float_compare(&args, f64::gt),
+        ">=." => float_compare(&args, f64::ge),
+        "to-float" => to_float(&env, args),
+        "floor" => float_to_int(&args, f64::floor),
+        "ceil" => float_to_int(&args, f64::ceil),
+        "round" => float_to_int(&args, f64::round),
+        "truncate" => float_to_int(&args, f64::trunc),
+        "parse-float" => parse_float(&env, args),
         "==" => number_eq(&env, args),
         "!=" => number_neq(&env, args),
         "[]" => access(&env, args),
         "map" => map(t_env, &env, args),
         "filter" => filter(t_env, &env, args),
         "range" => range(&env, args),
+        "fold" => fold(t_env, &env, args),
+        "reduce" => reduce(t_env, &env, args),
+        "nth" => nth(&env, args),
+        "head" => head(&env, args),
+        "tail" => tail(&env, args),
+        "cons" => cons(&env, args),
+        "append" => append(&env, args),
+        "flatten" => flatten(&env, args),
+        "reverse" => reverse(&env, args),
+        "zip" => zip(&env, args),
+        "enumerate" => enumerate(&env, args),
+        "sort-by" => sort_by(t_env, &env, args),
+        "any" => any(t_env, &env, args),
+        "all" => all(t_env, &env, args),
+        "find" => find(t_env, &env, args),
+        "flat-map" => flat_map(t_env, &env, args),
+        "concat" => str_concat(&env, args),
+        "length" => length(&env, args),
+        "substring" => str_substring(&env, args),
+        "split" => str_split(&env, args),
+        "join" => str_join(&env, args),
+        "trim" => str_trim(&env, args),
+        "contains" => str_contains(&env, args),
+        "starts-with" => str_starts_with(&env, args),
+        "replace" => str_replace(&env, args),
+        "upper" => str_upper(&env, args),
+        "lower" => str_lower(&env, args),
+        "parse-int" => str_parse_int(&env, args),
+        "str==" => str_eq(&env, args),
+        "str!=" => str_neq(&env, args),
+        "str<" => str_lt(&env, args),
+        "str>" => str_gt(&env, args),
+        "get" => dict_get(&env, args),
+        "insert" => dict_insert(&env, args),
+        "remove" => dict_remove(&env, args),
+        "keys" => dict_keys(&env, args),
+        "values" => dict_values(&env, args),
+        "entries" => dict_entries(&env, args),
         _ => Err(anyhow::anyhow!("{} is not external", name)),
     }?;
     Ok((res, env))
@@ -46,34 +112,171 @@ fn a_id(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
     Ok(a.clone())
 }
 
+/// an int value of a machine integer
+#[allow(clippy::useless_conversion)] // `Int` is `i64` without the `bigint` feature
+fn int(n: i64) -> Expr {
+    Expr::Literal(Value::Number(n.into()))
+}
+
+/// an int argument used as an index or a count
+fn small_int(e: &Expr) -> Result<i64> {
+    let n = e.literal()?.number()?;
+    n.to_i64().ok_or(anyhow::anyhow!("{} is too large", n))
+}
+
+/// an int argument used as an index. negative ones are 0 and too large ones are `usize::MAX`
+fn clamped_index(e: &Expr) -> Result<usize> {
+    let n = e.literal()?.number()?;
+    Ok(if n < Int::zero() {
+        0
+    } else {
+        n.to_usize().unwrap_or(usize::MAX)
+    })
+}
+
+/// `(op a b)` of ints. `None` of `op` is reported as `error`
+fn checked(args: &[Expr], op: impl Fn(&Int, &Int) -> Option<Int>, error: &str) -> Result<Expr> {
+    let a = args[0].literal()?.number()?;
+    let b = args[1].literal()?.number()?;
+    let n = op(&a, &b).ok_or(anyhow::anyhow!("{}: {} and {}", error, a, b))?;
+    Ok(Expr::Literal(Value::Number(n)))
+}
+
 fn number_plus(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
-    let a = &args[0].literal()?.number()?;
-    let b = &args[1].literal()?.number()?;
-    Ok(Expr::Literal(Value::Number(a + b)))
+    checked(&args, CheckedAdd::checked_add, "integer overflow")
 }
 
 fn number_minus(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
-    let a = &args[0].literal()?.number()?;
-    let b = &args[1].literal()?.number()?;
-    Ok(Expr::Literal(Value::Number(a - b)))
+    checked(&args, CheckedSub::checked_sub, "integer overflow")
+}
+
+fn number_mul(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    checked(&args, CheckedMul::checked_mul, "integer overflow")
 }
 
+/// rounds toward zero
+fn number_div(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    anyhow::ensure!(!args[1].literal()?.number()?.is_zero(), "division by zero");
+    checked(&args, CheckedDiv::checked_div, "integer overflow")
+}
+
+/// has the sign of the dividend
 fn number_mod(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
-    let a = &args[0].literal()?.number()?;
-    let b = &args[1].literal()?.number()?;
-    Ok(Expr::Literal(Value::Number(a % b)))
+    anyhow::ensure!(!args[1].literal()?.number()?.is_zero(), "division by zero");
+    checked(
+        &args,
+        |a, b| {
+            let quotient = CheckedDiv::checked_div(a, b)?;
+            CheckedSub::checked_sub(a, &CheckedMul::checked_mul(&quotient, b)?)
+        },
+        "integer overflow",
+    )
 }
 
-fn number_eq(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
-    let a = &args[0].literal()?.number()?;
-    let b = &args[1].literal()?.number()?;
-    Ok(Expr::Literal(Value::Bool(a == b)))
+/// the exponent must not be negative
+fn number_pow(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let a = args[0].literal()?.number()?;
+    let b = args[1].literal()?.number()?;
+    let error = anyhow::anyhow!("invalid exponent or integer overflow: {} and {}", a, b);
+    let n = b
+        .to_usize()
+        .and_then(|b| num_traits::checked_pow(a, b))
+        .ok_or(error)?;
+    Ok(Expr::Literal(Value::Number(n)))
 }
 
-fn number_neq(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+fn number_min(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
     let a = args[0].literal()?.number()?;
     let b = args[1].literal()?.number()?;
-    Ok(Expr::Literal(Value::Bool(a != b)))
+    Ok(Expr::Literal(Value::Number(a.min(b))))
+}
+
+fn number_max(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let a = args[0].literal()?.number()?;
+    let b = args[1].literal()?.number()?;
+    Ok(Expr::Literal(Value::Number(a.max(b))))
+}
+
+fn number_abs(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let a = args[0].literal()?.number()?;
+    let error = anyhow::anyhow!("integer overflow: {}", a);
+    let n = if a < Int::zero() {
+        CheckedSub::checked_sub(&Int::zero(), &a)
+    } else {
+        Some(a)
+    };
+    let n = n.ok_or(error)?;
+    Ok(Expr::Literal(Value::Number(n)))
+}
+
+fn compare(args: &[Expr], op: impl Fn(&Int, &Int) -> bool) -> Result<Expr> {
+    let a = args[0].literal()?.number()?;
+    let b = args[1].literal()?.number()?;
+    Ok(Expr::Literal(Value::Bool(op(&a, &b))))
+}
+
+fn number_lt(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    compare(&args, Int::lt)
+}
+
+fn number_le(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    compare(&args, Int::le)
+}
+
+fn number_gt(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    compare(&args, Int::gt)
+}
+
+fn number_ge(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    compare(&args, Int::ge)
+}
+
+fn number_eq(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    compare(&args, Int::eq)
+}
+
+fn number_neq(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    compare(&args, Int::ne)
+}
+
+/// IEEE 754 arithmetic: `(/. 1.0 0.0)` is infinity
+fn float_op(args: &[Expr], op: impl Fn(f64, f64) -> f64) -> Result<Expr> {
+    let a = args[0].literal()?.float()?;
+    let b = args[1].literal()?.float()?;
+    Ok(Expr::Literal(Value::Float(op(a, b))))
+}
+
+fn float_compare(args: &[Expr], op: impl Fn(&f64, &f64) -> bool) -> Result<Expr> {
+    let a = args[0].literal()?.float()?;
+    let b = args[1].literal()?.float()?;
+    Ok(Expr::Literal(Value::Bool(op(&a, &b))))
+}
+
+fn to_float(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let f = match args[0].literal()? {
+        Value::Number(n) => n
+            .to_f64()
+            .ok_or(anyhow::anyhow!("{} can't be converted to float", n))?,
+        Value::Float(f) => f,
+        v => return Err(anyhow::anyhow!("{} is not num", v)),
+    };
+    Ok(Expr::Literal(Value::Float(f)))
+}
+
+/// rounds by `op`. infinities, NaN and values out of the range of int are errors
+fn float_to_int(args: &[Expr], op: impl Fn(f64) -> f64) -> Result<Expr> {
+    let f = op(args[0].literal()?.float()?);
+    let n = Int::from_f64(f).ok_or(anyhow::anyhow!("{} can't be converted to int", f))?;
+    Ok(Expr::Literal(Value::Number(n)))
+}
+
+/// `:invalid-float` if `s` is not a float
+fn parse_float(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let s = args[0].literal()?.string()?;
+    Ok(Expr::Literal(match s.trim().parse::<f64>() {
+        Ok(f) => Value::Float(f),
+        Err(_) => Value::Atom("invalid-float".to_string()),
+    }))
 }
 
 fn bool_not(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
@@ -132,12 +335,359 @@ fn filter(t_env: &mut TypeEnv, env: &Environment, args: Vec<Expr>) -> Result<Exp
     Ok(Expr::Literal(Value::List(elements)))
 }
 
+fn apply(t_env: &mut TypeEnv, env: &Environment, f: &Expr, args: Vec<Expr>) -> Result<Expr> {
+    let (res, _) = FnApp::new(f.clone(), args).eval(t_env, env.clone())?;
+    Ok(res)
+}
+
+fn list(e: &Expr) -> Result<Vec<Expr>> {
+    Ok(e.literal()?.list()?.clone())
+}
+
+fn none() -> Expr {
+    Expr::Literal(Value::Atom("none".to_string()))
+}
+
+/// `(record (some : value))`, which is told apart from `:none` even if the value is `:none`
+fn some(value: Expr) -> Expr {
+    Expr::Literal(Value::Record(HashMap::from([("some".to_string(), value)])))
+}
+
+/// number of characters of a string, elements of a list or entries of a dict
+fn length(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let n = match args[0].literal()? {
+        Value::String(s) => s.chars().count(),
+        Value::List(elements) => elements.len(),
+        Value::Dict(entries) => entries.len(),
+        v => return Err(anyhow::anyhow!("{} has no length", v)),
+    };
+    Ok(int(n as i64))
+}
+
+fn fold(t_env: &mut TypeEnv, env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let mut acc = args[1].clone();
+    for e in list(&args[2])? {
+        acc = apply(t_env, env, &args[0], vec![acc, e])?;
+    }
+    Ok(acc)
+}
+
+/// `(record (some : result))`, or `:none` for the empty list
+fn reduce(t_env: &mut TypeEnv, env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let mut elements = list(&args[1])?.into_iter();
+    let Some(mut acc) = elements.next() else {
+        return Ok(none());
+    };
+    for e in elements {
+        acc = apply(t_env, env, &args[0], vec![acc, e])?;
+    }
+    Ok(some(acc))
+}
+
+/// `(record (some : element))`, or `:none` if the index is out of range
+fn nth(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let elements = list(&args[0])?;
+    let i = args[1].literal()?.number()?;
+    Ok(i.to_usize()
+        .and_then(|i| elements.get(i).cloned())
+        .map_or_else(none, some))
+}
+
+/// `(record (some : element))`, or `:none` for the empty list
+fn head(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    Ok(list(&args[0])?.first().cloned().map_or_else(none, some))
+}
+
+/// the empty list for the empty list
+fn tail(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let elements = list(&args[0])?;
+    Ok(Expr::Literal(Value::List(
+        elements.into_iter().skip(1).collect(),
+    )))
+}
+
+fn cons(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let mut elements = vec![args[0].clone()];
+    elements.extend(list(&args[1])?);
+    Ok(Expr::Literal(Value::List(elements)))
+}
+
+fn append(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let mut elements = list(&args[0])?;
+    elements.extend(list(&args[1])?);
+    Ok(Expr::Literal(Value::List(elements)))
+}
+
+fn flatten(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let elements = list(&args[0])?
+        .iter()
+        .map(list)
+        .collect::<Result<Vec<_>>>()?
+        .concat();
+    Ok(Expr::Literal(Value::List(elements)))
+}
+
+fn reverse(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let mut elements = list(&args[0])?;
+    elements.reverse();
+    Ok(Expr::Literal(Value::List(elements)))
+}
+
+/// stops at the end of the shorter list
+fn zip(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let elements = list(&args[0])?
+        .into_iter()
+        .zip(list(&args[1])?)
+        .map(|(a, b)| {
+            Expr::Literal(Value::Record(HashMap::from([
+                ("first".to_string(), a),
+                ("second".to_string(), b),
+            ])))
+        })
+        .collect();
+    Ok(Expr::Literal(Value::List(elements)))
+}
+
+fn enumerate(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let elements = list(&args[0])?
+        .into_iter()
+        .enumerate()
+        .map(|(i, e)| {
+            Expr::Literal(Value::Record(HashMap::from([
+                ("index".to_string(), int(i as i64)),
+                ("value".to_string(), e),
+            ])))
+        })
+        .collect();
+    Ok(Expr::Literal(Value::List(elements)))
+}
+
+/// stable sort by the int key
+fn sort_by(t_env: &mut TypeEnv, env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let mut keyed = list(&args[1])?
+        .into_iter()
+        .map(|e| {
+            let key = apply(t_env, env, &args[0], vec![e.clone()])?
+                .literal()?
+                .number()?;
+            Ok((key, e))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // keys are not `Copy` with the `bigint` feature
+    #[allow(clippy::unnecessary_sort_by)]
+    keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(Expr::Literal(Value::List(
+        keyed.into_iter().map(|(_, e)| e).collect(),
+    )))
+}
+
+fn any(t_env: &mut TypeEnv, env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    for e in list(&args[1])? {
+        if apply(t_env, env, &args[0], vec![e])?.literal()?.boolean()? {
+            return Ok(Expr::Literal(Value::Bool(true)));
+        }
+    }
+    Ok(Expr::Literal(Value::Bool(false)))
+}
+
+fn all(t_env: &mut TypeEnv, env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    for e in list(&args[1])? {
+        if !apply(t_env, env, &args[0], vec![e])?.literal()?.boolean()? {
+            return Ok(Expr::Literal(Value::Bool(false)));
+        }
+    }
+    Ok(Expr::Literal(Value::Bool(true)))
+}
+
+/// `(record (some : element))`, or `:none` if no element satisfies the predicate
+fn find(t_env: &mut TypeEnv, env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    for e in list(&args[1])? {
+        if apply(t_env, env, &args[0], vec![e.clone()])?
+            .literal()?
+            .boolean()?
+        {
+            return Ok(some(e));
+        }
+    }
+    Ok(none())
+}
+
+fn flat_map(t_env: &mut TypeEnv, env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let mut elements = vec![];
+    for e in list(&args[1])? {
+        elements.extend(list(&apply(t_env, env, &args[0], vec![e])?)?);
+    }
+    Ok(Expr::Literal(Value::List(elements)))
+}
+
 fn range(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
-    let start = args[0].literal()?.number()?;
-    let end = args[1].literal()?.number()?;
+    let start = small_int(&args[0])?;
+    let end = small_int(&args[1])?;
+    Ok(Expr::Literal(Value::List((start..end).map(int).collect())))
+}
+
+fn str_concat(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let a = args[0].literal()?.string()?;
+    let b = args[1].literal()?.string()?;
+    Ok(Expr::Literal(Value::String(a + &b)))
+}
+
+/// characters in `[start, end)`. indices out of the string are clamped
+fn str_substring(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let s = args[0].literal()?.string()?;
+    let start = clamped_index(&args[1])?;
+    let end = clamped_index(&args[2])?;
+    Ok(Expr::Literal(Value::String(
+        s.chars()
+            .skip(start)
+            .take(end.saturating_sub(start))
+            .collect(),
+    )))
+}
+
+fn str_split(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let s = args[0].literal()?.string()?;
+    let sep = args[1].literal()?.string()?;
     Ok(Expr::Literal(Value::List(
-        (start..end)
-            .map(|i| Expr::Literal(Value::Number(i)))
+        s.split(sep.as_str())
+            .map(|part| Expr::Literal(Value::String(part.to_string())))
             .collect(),
     )))
 }
+
+fn str_join(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let parts = args[0]
+        .literal()?
+        .list()?
+        .iter()
+        .map(|part| part.literal()?.string())
+        .collect::<Result<Vec<_>>>()?;
+    let sep = args[1].literal()?.string()?;
+    Ok(Expr::Literal(Value::String(parts.join(&sep))))
+}
+
+fn str_trim(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let s = args[0].literal()?.string()?;
+    Ok(Expr::Literal(Value::String(s.trim().to_string())))
+}
+
+fn str_contains(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let s = args[0].literal()?.string()?;
+    let pat = args[1].literal()?.string()?;
+    Ok(Expr::Literal(Value::Bool(s.contains(pat.as_str()))))
+}
+
+fn str_starts_with(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let s = args[0].literal()?.string()?;
+    let prefix = args[1].literal()?.string()?;
+    Ok(Expr::Literal(Value::Bool(s.starts_with(prefix.as_str()))))
+}
+
+fn str_replace(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let s = args[0].literal()?.string()?;
+    let from = args[1].literal()?.string()?;
+    let to = args[2].literal()?.string()?;
+    Ok(Expr::Literal(Value::String(s.replace(from.as_str(), &to))))
+}
+
+fn str_upper(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let s = args[0].literal()?.string()?;
+    Ok(Expr::Literal(Value::String(s.to_uppercase())))
+}
+
+fn str_lower(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let s = args[0].literal()?.string()?;
+    Ok(Expr::Literal(Value::String(s.to_lowercase())))
+}
+
+/// returns `:invalid-int` if `s` is not an integer
+fn str_parse_int(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let s = args[0].literal()?.string()?;
+    Ok(Expr::Literal(match s.trim().parse::<Int>() {
+        Ok(n) => Value::Number(n),
+        Err(_) => Value::Atom("invalid-int".to_string()),
+    }))
+}
+
+fn str_eq(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let a = args[0].literal()?.string()?;
+    let b = args[1].literal()?.string()?;
+    Ok(Expr::Literal(Value::Bool(a == b)))
+}
+
+fn str_neq(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let a = args[0].literal()?.string()?;
+    let b = args[1].literal()?.string()?;
+    Ok(Expr::Literal(Value::Bool(a != b)))
+}
+
+/// lexicographic order
+fn str_lt(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let a = args[0].literal()?.string()?;
+    let b = args[1].literal()?.string()?;
+    Ok(Expr::Literal(Value::Bool(a < b)))
+}
+
+fn str_gt(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let a = args[0].literal()?.string()?;
+    let b = args[1].literal()?.string()?;
+    Ok(Expr::Literal(Value::Bool(a > b)))
+}
+
+fn dict(e: &Expr) -> Result<Vec<(Expr, Expr)>> {
+    Ok(e.literal()?.dict()?.clone())
+}
+
+/// replaces the value of `key` in place, or appends the entry if `key` is new
+pub fn insert_entry(entries: &mut Vec<(Expr, Expr)>, key: Expr, value: Expr) {
+    match entries.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, v)) => *v = value,
+        None => entries.push((key, value)),
+    }
+}
+
+/// `(record (some : value))`, or `:none` if the key is absent
+fn dict_get(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    Ok(dict(&args[0])?
+        .into_iter()
+        .find(|(k, _)| *k == args[1])
+        .map_or_else(none, |(_, v)| some(v)))
+}
+
+/// a new dict. the given one is left unchanged
+fn dict_insert(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let mut entries = dict(&args[0])?;
+    insert_entry(&mut entries, args[1].clone(), args[2].clone());
+    Ok(Expr::Literal(Value::Dict(entries)))
+}
+
+/// a new dict without the key. removing an absent key is not an error
+fn dict_remove(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let mut entries = dict(&args[0])?;
+    entries.retain(|(k, _)| *k != args[1]);
+    Ok(Expr::Literal(Value::Dict(entries)))
+}
+
+/// in insertion order
+fn dict_keys(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let keys = dict(&args[0])?.into_iter().map(|(k, _)| k).collect();
+    Ok(Expr::Literal(Value::List(keys)))
+}
+
+fn dict_values(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let values = dict(&args[0])?.into_iter().map(|(_, v)| v).collect();
+    Ok(Expr::Literal(Value::List(values)))
+}
+
+fn dict_entries(_env: &Environment, args: Vec<Expr>) -> Result<Expr> {
+    let entries = dict(&args[0])?
+        .into_iter()
+        .map(|(k, v)| {
+            Expr::Literal(Value::Record(HashMap::from([
+                ("key".to_string(), k),
+                ("value".to_string(), v),
+            ])))
+        })
+        .collect();
+    Ok(Expr::Literal(Value::List(entries)))
+}
@@ -0,0 +1,410 @@
+use crate::{
+    environment::Environment,
+    eval::{substitute, Eval},
+    parse,
+    prelude::bundled,
+};
+use anyhow::{anyhow, Result};
+use ast::ast::{Expr, Program};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+use structural_typesystem::{
+    type_check::TypeCheck,
+    type_env::{ModuleInterface, TypeEnv},
+    types::Id,
+};
+
+const MODULE_EXTENSION: &str = "sexp";
+
+/// values of an evaluated module
+#[derive(Debug, Clone)]
+pub struct ModuleValues {
+    /// qualifier of the exported names: `name.x`
+    pub name: String,
+    pub exports: Vec<String>,
+    /// every binding at the top level of the module, including private ones.
+    /// references to them are renamed by [module_binding]
+    pub bindings: HashMap<String, Expr>,
+}
+
+/// loads modules imported by a program.
+/// each module is type checked and evaluated once, and then cached by its resolved path.
+pub struct ModuleLoader {
+    pub type_env: TypeEnv,
+    modules: HashMap<String, ModuleValues>,
+    /// modules being loaded, used to detect import cycles
+    loading: Vec<String>,
+    /// directories given by `-I`
+    include_dirs: Vec<PathBuf>,
+}
+
+impl Default for ModuleLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ModuleLoader {
+    pub fn new() -> Self {
+        Self {
+            type_env: TypeEnv::default(),
+            modules: HashMap::new(),
+            loading: vec![],
+            include_dirs: vec![],
+        }
+    }
+
+    /// adds a directory searched after the one of the importing file
+    pub fn include_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.include_dirs.push(dir.into());
+        self
+    }
+
+    /// searches `path` (`std/list` or `std/list.sexp`) in the directory of the importing module,
+    /// the include directories and the bundled std in this order.
+    /// returns the canonical path of the file, or the import path of the bundled module.
+    fn resolve(&self, path: &str, importer: Option<&str>) -> Result<String> {
+        let mut file = PathBuf::from(path);
+        if file.extension().is_none() {
+            file.set_extension(MODULE_EXTENSION);
+        }
+        let mut candidates = vec![];
+        if let Some(importer) = importer {
+            let candidate = Path::new(importer)
+                .parent()
+                .unwrap_or(Path::new(""))
+                .join(&file);
+            // bundled modules import each other relatively as well
+            if bundled(importer).is_some() {
+                let candidate = candidate.display().to_string();
+                if bundled(&candidate).is_some() {
+                    return Ok(candidate);
+                }
+            } else if candidate.is_file() {
+                return canonicalize(&candidate);
+            }
+            candidates.push(candidate);
+        }
+        for dir in &self.include_dirs {
+            let candidate = dir.join(&file);
+            if candidate.is_file() {
+                return canonicalize(&candidate);
+            }
+            candidates.push(candidate);
+        }
+        let bundled_path = file.display().to_string();
+        if bundled(&bundled_path).is_some() {
+            return Ok(bundled_path);
+        }
+        Err(anyhow!(
+            "module {} not found in {}",
+            path,
+            candidates
+                .iter()
+                .map(|c| c.display().to_string())
+                .chain(std::iter::once("the bundled std".to_string()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    }
+
+    /// type checks and evaluates the program in `path`, and returns its value
+    pub fn run(&mut self, path: &Path) -> Result<Expr> {
+        let path = canonicalize(path)?;
+        let program = read(&path)?;
+        let (ret, _, _) = self.load_program(&path, Some(&path), program)?;
+        Ok(ret)
+    }
+
+    /// type checks and evaluates a program given as a string.
+    /// imports are searched in the include directories and the bundled root.
+    pub fn run_str(&mut self, program: &str) -> Result<Expr> {
+        let (ret, _, _) = self.load_program("main", None, parse(program)?)?;
+        Ok(ret)
+    }
+
+    fn load(&mut self, path: &str) -> Result<()> {
+        if self.modules.contains_key(path) {
+            return Ok(());
+        }
+        let program = read(path)?;
+        let declared = program
+            .module()
+            .map(|module| (module.name.clone(), module.exports()));
+        let (_, types, env) = self.load_program(path, Some(path), program)?;
+        // free references to the module's bindings are renamed by [module_binding]
+        let renames = env
+            .variables
+            .keys()
+            .map(|name| (name.clone(), Expr::Variable(module_binding(path, name))))
+            .collect::<HashMap<_, _>>();
+        let bindings = env
+            .variables
+            .iter()
+            .map(|(name, value)| (name.clone(), substitute(value, &renames)))
+            .collect();
+        // a file without `(module ...)` exports every binding at its top level
+        let (name, exports) = declared.unwrap_or_else(|| {
+            let mut exports = types
+                .keys()
+                .filter(|name| !is_qualified(name))
+                .cloned()
+                .collect::<Vec<_>>();
+            exports.sort();
+            (module_name(path), exports)
+        });
+        let exported_types = exports
+            .iter()
+            .map(|name| (name.clone(), types[name]))
+            .collect::<BTreeMap<_, _>>();
+        self.type_env.register_module(
+            path,
+            ModuleInterface {
+                name: name.clone(),
+                exports: exported_types,
+                types: self.type_env.module_types(path),
+            },
+        );
+        self.modules.insert(
+            path.to_string(),
+            ModuleValues {
+                name,
+                exports,
+                bindings,
+            },
+        );
+        Ok(())
+    }
+
+    fn load_program(
+        &mut self,
+        path: &str,
+        file: Option<&str>,
+        mut program: Program,
+    ) -> Result<(Expr, HashMap<String, Id>, Environment)> {
+        if let Some(i) = self.loading.iter().position(|p| p == path) {
+            let cycle = self.loading[i..]
+                .iter()
+                .chain(std::iter::once(&path.to_string()))
+                .cloned()
+                .collect::<Vec<_>>();
+            return Err(anyhow!("import cycle: {}", cycle.join(" -> ")));
+        }
+        self.loading.push(path.to_string());
+        let res = self.load_imports(&mut program, file).and_then(|_| {
+            let (_, types) = self.type_env.with_module(path, |env| program.synth(env))?;
+            let mut env = Environment::new(None);
+            env.modules = self.modules.clone();
+            let (ret, env) = program.eval(&mut self.type_env, env)?;
+            Ok((ret, types, env))
+        });
+        self.loading.pop();
+        res
+    }
+
+    /// loads imported modules and replaces their paths with the resolved ones
+    fn load_imports(&mut self, program: &mut Program, file: Option<&str>) -> Result<()> {
+        for import in program.imports_mut() {
+            let path = self.resolve(&import.path, file)?;
+            self.load(&path)?;
+            import.path = path;
+        }
+        Ok(())
+    }
+}
+
+/// modules on disk are identified by their canonical path
+fn canonicalize(path: &Path) -> Result<String> {
+    path.canonicalize()
+        .map(|path| path.display().to_string())
+        .map_err(|e| anyhow!("failed to open {}: {}", path.display(), e))
+}
+
+fn read(path: &str) -> Result<Program> {
+    if let Some(source) = bundled(path) {
+        return parse(source);
+    }
+    let mut f = File::open(path)?;
+    let mut program = String::new();
+    f.read_to_string(&mut program)?;
+    parse(&program)
+}
+
+/// `module.name`. a trailing dot belongs to the name: `+.`, `float.+.`
+fn is_qualified(name: &str) -> bool {
+    name.strip_suffix('.').unwrap_or(name).contains('.')
+}
+
+/// name of the binding `name` of the module in `path` in the environments of its importers.
+/// functions have no closures, so the bindings they refer to are renamed to be neither
+/// visible to nor shadowed by the importers' own bindings
+pub(crate) fn module_binding(path: &str, name: &str) -> String {
+    format!("{}:{}", path, name)
+}
+
+/// `/path/to/list.sexp` -> `list`
+fn module_name(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or(path.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ModuleLoader;
+    use crate::tests::setup;
+    use anyhow::Result;
+    use ast::ast::{Expr, Value};
+    use std::path::PathBuf;
+
+    fn codes_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../codes")
+    }
+
+    fn loader() -> ModuleLoader {
+        ModuleLoader::new().include_dir(codes_dir())
+    }
+
+    fn should_fail(program: &str, error: &str) {
+        setup();
+        let err = loader().run_str(program).err().map(|e| e.to_string());
+        assert!(
+            err.as_ref().is_some_and(|e| e.contains(error)),
+            "{:?} does not contain {}",
+            err,
+            error
+        );
+    }
+
+    #[test]
+    fn import() -> Result<()> {
+        setup();
+        let ret = loader().run_str(
+            r#"(import modules/geometry (perimeter))
+            (import modules/geometry)
+            (perimeter (geometry.perimeter 1 2) 1)"#,
+        )?;
+        assert_eq!(ret, Expr::Literal(Value::Number(14.into())));
+        Ok(())
+    }
+
+    #[test]
+    fn private_binding() {
+        should_fail(
+            "(import modules/geometry (double))",
+            "double is not exported by module geometry",
+        );
+        should_fail(
+            "(import modules/geometry) (geometry.double 1)",
+            "unbound variable geometry.double",
+        );
+    }
+
+    #[test]
+    fn opaque_cast() -> Result<()> {
+        setup();
+        let ret = loader().run_str("(import modules/counter *) (value (next (next zero)))")?;
+        assert_eq!(ret, Expr::Literal(Value::Number(2.into())));
+        // the defining module casts to its opaque types by their representation
+        let ret = loader().run_str("(import modules/counter *) (value (of-dyn 3))")?;
+        assert_eq!(ret, Expr::Literal(Value::Number(3.into())));
+        should_fail(
+            "(import modules/counter *) (of-dyn 'a')",
+            "'a' is not counter.counter",
+        );
+        // values of opaque types can't be forged outside of the defining module
+        should_fail(
+            "(import modules/counter *) (let d : dyn 41) (let c : counter d) (value (next c))",
+            "cast failed at d in main: 41 is not counter.counter",
+        );
+        let ret = loader().run_str("(import modules/counter *) (cast counter 41)")?;
+        assert_eq!(ret, Expr::Literal(Value::Atom("invalid-cast".to_string())));
+        Ok(())
+    }
+
+    #[test]
+    fn module_type_names() -> Result<()> {
+        setup();
+        // type names are qualified unless imported by name
+        should_fail(
+            "(import modules/counter) (let c : counter counter.zero)",
+            "counter.counter is not subtype of counter",
+        );
+        let ret = loader().run_str(
+            "(import modules/counter) (let c : counter.counter counter.zero) (counter.value c)",
+        )?;
+        assert_eq!(ret, Expr::Literal(Value::Number(0.into())));
+        let ret = loader().run_str(
+            "(import modules/counter (counter zero value)) (let c : counter zero) (value c)",
+        )?;
+        assert_eq!(ret, Expr::Literal(Value::Number(0.into())));
+        Ok(())
+    }
+
+    #[test]
+    fn module_newtypes() -> Result<()> {
+        setup();
+        let ret = loader().run_str(
+            "(import modules/ids) (let u : ids.user-id (ids.wrap-user-id 1)) (ids.unwrap-user-id u)",
+        )?;
+        assert_eq!(ret, Expr::Literal(Value::Number(1.into())));
+        should_fail(
+            "(import modules/ids) (let u : user-id (ids.wrap-user-id 1))",
+            "ids.user-id is not subtype of user-id",
+        );
+        should_fail(
+            "(import modules/ids *) (let o : order-id (wrap-user-id 1))",
+            "ids.user-id is not subtype of ids.order-id",
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn private_binding_isolated() -> Result<()> {
+        setup();
+        // exported functions use the private `double` of the module, not the importer's
+        let ret = loader().run_str(
+            r#"(include std/prelude.sexp)
+            (let double (fn x (* x 100)))
+            (import modules/geometry (perimeter))
+            (perimeter 1 2)"#,
+        )?;
+        assert_eq!(ret, Expr::Literal(Value::Number(6.into())));
+        let ret = loader().run_str(
+            r#"(include std/prelude.sexp)
+            (import modules/geometry (perimeter))
+            (let double (fn x (* x 100)))
+            (+ (perimeter 1 2) (double 1))"#,
+        )?;
+        assert_eq!(ret, Expr::Literal(Value::Number(106.into())));
+        Ok(())
+    }
+
+    #[test]
+    fn import_cycle() {
+        should_fail("(import modules/cycle_a)", "import cycle");
+    }
+
+    #[test]
+    fn search_path() -> Result<()> {
+        setup();
+        let lib = codes_dir().join("modules/lib");
+        // the directory of the importing file comes first
+        let ret = ModuleLoader::new()
+            .include_dir(&lib)
+            .run(&codes_dir().join("modules/answer_main.sexp"))?;
+        assert_eq!(ret, Expr::Literal(Value::Number(2.into())));
+        // without an importing file, include directories are searched
+        let ret = ModuleLoader::new()
+            .include_dir(&lib)
+            .run_str("(import answer *) answer")?;
+        assert_eq!(ret, Expr::Literal(Value::Number(1.into())));
+        should_fail("(import missing)", "module missing not found");
+        Ok(())
+    }
+}
@@ -1,23 +1,14 @@
-use crate::{environment::Environment, eval::Eval};
+use crate::loader::ModuleLoader;
 use anyhow::Result;
-use ast::{
-    ast::{Expr, Program},
-    into_ast::into_ast,
-};
-use std::{env, fs::File, io::Read, path::PathBuf};
-use structural_typesystem::{type_check::TypeCheck, type_env::TypeEnv};
+use ast::{ast::Program, into_ast::into_ast};
+use std::{env, path::PathBuf};
 use symbolic_expressions::parser::parse_str;
 
 pub mod environment;
 pub mod eval;
 pub mod externals;
-
-fn include(path: &PathBuf) -> Result<Program> {
-    let mut f = File::open(path)?;
-    let mut program = String::new();
-    f.read_to_string(&mut program)?;
-    parse(&program)
-}
+pub mod loader;
+pub mod prelude;
 
 fn parse(program: &str) -> Result<Program> {
     let program = program
@@ -42,23 +33,26 @@ pub fn setup_logger() {
 }
 
 fn main() -> Result<()> {
-    let args = env::args().collect::<Vec<_>>();
-    let ml_path = args.get(1).ok_or(anyhow::anyhow!("require ml_path"))?;
-    let program = include(&PathBuf::from(ml_path))?;
-
-    let mut type_env = TypeEnv::default();
-    let mut env = Environment::new(None);
-
-    setup_logger();
-    for e in program.0.iter() {
-        if let Expr::Include(path) = e {
-            let module = include(&PathBuf::from(path))?;
-            module.type_check(&mut type_env)?;
-            (_, env) = module.eval(&mut type_env, env)?;
+    // interpreter [-I dir]... ml_path
+    let mut loader = ModuleLoader::new();
+    let mut ml_path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "-I" {
+            let dir = args
+                .next()
+                .ok_or(anyhow::anyhow!("-I requires a directory"))?;
+            loader = loader.include_dir(dir);
+        } else if let Some(dir) = arg.strip_prefix("-I") {
+            loader = loader.include_dir(dir);
+        } else {
+            ml_path = Some(arg);
         }
     }
-    program.type_check(&mut type_env)?;
-    let (ret, _) = program.eval(&mut type_env, env)?;
+    let ml_path = ml_path.ok_or(anyhow::anyhow!("require ml_path"))?;
+
+    setup_logger();
+    let ret = loader.run(&PathBuf::from(ml_path))?;
     log::debug!("{}", &ret);
     Ok(())
 }
@@ -0,0 +1,324 @@
+/// std modules compiled into the interpreter. key is the import path
+const BUNDLED: [(&str, &str); 8] = [
+    ("std/prelude.sexp", include_str!("../../std/prelude.sexp")),
+    ("std/int.sexp", include_str!("../../std/int.sexp")),
+    ("std/float.sexp", include_str!("../../std/float.sexp")),
+    ("std/bool.sexp", include_str!("../../std/bool.sexp")),
+    ("std/str.sexp", include_str!("../../std/str.sexp")),
+    ("std/vec.sexp", include_str!("../../std/vec.sexp")),
+    ("std/record.sexp", include_str!("../../std/record.sexp")),
+    ("std/dict.sexp", include_str!("../../std/dict.sexp")),
+];
+
+/// source of a bundled std module
+pub fn bundled(path: &str) -> Option<&'static str> {
+    BUNDLED
+        .iter()
+        .find(|(p, _)| *p == path)
+        .map(|(_, source)| *source)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{eval::tests::should_eval, loader::ModuleLoader, tests::setup};
+    use anyhow::Result;
+
+    fn should_fail(program: &str, error: &str) {
+        setup();
+        let err = ModuleLoader::new()
+            .run_str(program)
+            .err()
+            .map(|e| e.to_string());
+        assert!(
+            err.as_ref().is_some_and(|e| e.contains(error)),
+            "{:?} does not contain {}",
+            err,
+            error
+        );
+    }
+
+    #[test]
+    fn int() -> Result<()> {
+        should_eval("(+ 1 2)", "3")?;
+        should_eval("(- 3 1)", "2")?;
+        should_eval("(% 7 3)", "1")?;
+        should_eval("(== 1 1)", "true")?;
+        should_eval("(!= 1 1)", "false")?;
+        should_eval("(* 3 4)", "12")?;
+        should_eval("(/ 7 2)", "3")?;
+        should_eval("(== (/ (- 0 7) 2) (- 0 3))", "true")?;
+        should_eval("(== (% (- 0 7) 3) (- 0 1))", "true")?;
+        should_eval("(< 1 2)", "true")?;
+        should_eval("(<= 2 2)", "true")?;
+        should_eval("(> 1 2)", "false")?;
+        should_eval("(>= 1 2)", "false")?;
+        should_eval("(abs (- 0 3))", "3")?;
+        should_eval("(min 1 2)", "1")?;
+        should_eval("(max 1 2)", "2")?;
+        should_eval("(pow 2 10)", "1024")?;
+        should_fail("(include std/prelude.sexp) (/ 1 0)", "division by zero");
+        should_fail("(include std/prelude.sexp) (% 1 0)", "division by zero");
+        should_fail(
+            "(include std/prelude.sexp) (pow 2 (- 0 1))",
+            "invalid exponent or integer overflow: 2 and -1",
+        );
+        #[cfg(not(feature = "bigint"))]
+        should_fail(
+            "(include std/prelude.sexp) (* (pow 2 62) 2)",
+            "integer overflow: 4611686018427387904 and 2",
+        );
+        #[cfg(feature = "bigint")]
+        should_eval("(* (pow 2 62) 4)", "18446744073709551616")?;
+        should_fail(
+            "(import std/int) (int.+ 1 true)",
+            "true is not subtype of int",
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn float() -> Result<()> {
+        should_eval("(+. 1.5 -0.25)", "1.25")?;
+        should_eval("(*. 2.0 0.5)", "1.0")?;
+        should_eval("(/. 1.0 4.0)", "0.25")?;
+        should_eval("(<. 1.0 1.5)", "true")?;
+        should_eval("(to-float -3)", "-3.0")?;
+        should_eval("(to-float 1.5)", "1.5")?;
+        should_eval("(floor -1.5)", "-2")?;
+        should_eval("(ceil 1.2)", "2")?;
+        should_eval("(round 2.5)", "3")?;
+        should_eval("(truncate -1.7)", "-1")?;
+        should_eval("(parse-float '0.5')", "0.5")?;
+        should_eval("(parse-float 'x')", ":invalid-float")?;
+        should_fail(
+            "(include std/prelude.sexp) (+ 1 1.5)",
+            "1.5 is not subtype of int",
+        );
+        should_fail(
+            "(include std/prelude.sexp) (truncate (/. 1.0 0.0))",
+            "inf can't be converted to int",
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn bool() -> Result<()> {
+        should_eval("(not true)", "false")?;
+        should_eval("(& true false)", "false")?;
+        should_eval("(| true false)", "true")?;
+        should_fail("(import std/bool) (bool.not 1)", "1 is not subtype of bool");
+        Ok(())
+    }
+
+    #[test]
+    fn str() -> Result<()> {
+        should_eval("(to_string 1)", "'1'")?;
+        should_eval("(concat 'fizz' 'buzz')", "'fizzbuzz'")?;
+        should_eval("(length 'héllo')", "5")?;
+        should_eval("(substring 'hello' 1 3)", "'el'")?;
+        should_eval("(substring 'hello' 3 10)", "'lo'")?;
+        should_eval("(substring 'hello' 3 1)", "''")?;
+        should_eval("(split 'a,b,c' ',')", "(vec 'a' 'b' 'c')")?;
+        should_eval("(join (split 'a,b' ',') '-')", "'a-b'")?;
+        should_eval("(trim '\ta\t')", "'a'")?;
+        should_eval("(contains 'fizzbuzz' 'zb')", "true")?;
+        should_eval("(starts-with 'fizzbuzz' 'buzz')", "false")?;
+        should_eval("(replace 'a-b-c' '-' '+')", "'a+b+c'")?;
+        should_eval("(upper 'abc')", "'ABC'")?;
+        should_eval("(lower 'ABC')", "'abc'")?;
+        should_eval("(parse-int '42')", "42")?;
+        should_eval("(parse-int 'x')", ":invalid-int")?;
+        should_eval("(str== 'a' 'a')", "true")?;
+        should_eval("(str!= 'a' 'a')", "false")?;
+        should_eval("(str< 'a' 'b')", "true")?;
+        should_eval("(str> 'a' 'b')", "false")?;
+        should_fail(
+            "(import std/str (length)) (length 1)",
+            "1 is not subtype of str",
+        );
+        should_fail(
+            "(include std/prelude.sexp) (let n : int (parse-int '1'))",
+            "(| int :invalid-int) is not subtype of int",
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn vec() -> Result<()> {
+        should_eval("(range 0 3)", "(vec 0 1 2)")?;
+        should_eval("(map (fn x (+ x 1)) (range 1 3))", "(vec 2 3)")?;
+        should_eval("(filter (fn x (== x 1)) (range 1 3))", "(vec 1)")?;
+        should_eval("(fold (fn acc x (+ acc x)) 0 (range 1 4))", "6")?;
+        should_eval(
+            "(reduce (fn a b (+ a b)) (range 1 4))",
+            "(record (some : 6))",
+        )?;
+        should_eval("(reduce (fn a b (+ a b)) (range 0 0))", ":none")?;
+        should_eval("(length (range 0 3))", "3")?;
+        should_eval("(nth (range 0 3) 1)", "(record (some : 1))")?;
+        should_eval("(nth (range 0 3) 3)", ":none")?;
+        should_eval("(head (range 0 0))", ":none")?;
+        // elements which are `:none` are told apart from the absence of elements
+        should_eval("(head (vec :none))", "(record (some : :none))")?;
+        should_eval("(tail (range 0 3))", "(vec 1 2)")?;
+        should_eval("(tail (range 0 0))", "(vec)")?;
+        should_eval("(cons 0 (range 1 3))", "(vec 0 1 2)")?;
+        should_eval("(append (range 0 2) (range 2 4))", "(vec 0 1 2 3)")?;
+        should_eval(
+            "(import std/vec) (vec.concat (vec (range 0 2) (range 2 3)))",
+            "(vec 0 1 2)",
+        )?;
+        should_eval("(reverse (range 0 3))", "(vec 2 1 0)")?;
+        should_eval(
+            "(zip (range 0 2) (vec true))",
+            "(vec (record (first : 0) (second : true)))",
+        )?;
+        should_eval(
+            "(enumerate (vec 'a'))",
+            "(vec (record (index : 0) (value : 'a')))",
+        )?;
+        should_eval("(sort-by (fn x (- 0 x)) (range 0 3))", "(vec 2 1 0)")?;
+        should_eval("(any (fn x (== x 2)) (range 0 3))", "true")?;
+        should_eval("(all (fn x (== x 2)) (range 0 0))", "true")?;
+        should_eval("(find (fn x (== x 2)) (range 0 3))", "(record (some : 2))")?;
+        should_eval("(find (fn x (== x 5)) (range 0 3))", ":none")?;
+        should_eval("(flat-map (fn x (vec x x)) (range 0 2))", "(vec 0 0 1 1)")?;
+        should_eval("(reverse (vec 1 'a' :b))", "(vec :b 'a' 1)")?;
+        should_fail(
+            "(import std/vec (map)) (map 1 (vec 1))",
+            "1 is not subtype of ((a) -> b)",
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn record() -> Result<()> {
+        should_eval("([] (record (a : 1)) :a)", "1")?;
+        should_fail(
+            "(import std/record *) ([] (record (a : 1)) :b)",
+            ":b is not subtype of (keyof (record (a : 1)))",
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn dict() -> Result<()> {
+        should_eval(
+            "(get (dict ('a' : 1) ('b' : 2)) 'b')",
+            "(record (some : 2))",
+        )?;
+        should_eval("(get (dict (:a : :none)) :a)", "(record (some : :none))")?;
+        should_eval("(get (dict (:a : 1)) :b)", ":none")?;
+        should_eval("(insert (dict ('a' : 1)) 'a' 2)", "(dict ('a' : 2))")?;
+        should_eval(
+            "(let ((d (dict ('a' : 1))) (e (insert d 'b' 2))) (keys d))",
+            "(vec 'a')",
+        )?;
+        should_eval("(remove (dict ('a' : 1)) 'b')", "(dict ('a' : 1))")?;
+        should_eval(
+            "(keys (insert (dict (:b : 1) (:a : 2)) :c 3))",
+            "(vec :b :a :c)",
+        )?;
+        should_eval("(values (dict ('a' : 1) ('a' : 2)))", "(vec 2)")?;
+        should_eval(
+            "(entries (dict ('a' : 1)))",
+            "(vec (record (key : 'a') (value : 1)))",
+        )?;
+        should_eval("(length (dict ('a' : 1)))", "1")?;
+        should_fail(
+            "(import std/dict *) (get (dict ('a' : 1)) :a)",
+            ":a is not subtype of str",
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn dyn_cast() -> Result<()> {
+        should_eval("(let d : dyn 1) (+ d 2)", "3")?;
+        should_eval("(let f : dyn (fn x (+ x 1))) (f 2)", "3")?;
+        should_eval(
+            "(let d : dyn (vec 1 2)) (let xs : (vec int) d) xs",
+            "(vec 1 2)",
+        )?;
+        should_fail(
+            "(include std/prelude.sexp) (let d : dyn 'a') (+ d 1)",
+            "cast failed at d in main: 'a' is not int",
+        );
+        should_fail(
+            "(include std/prelude.sexp) (let d : dyn (vec 1 'a')) (let xs : (vec int) d)",
+            "cast failed at d in main: (vec 1 'a') is not (vec int)",
+        );
+        should_fail(
+            "(include std/prelude.sexp) (let r : (record (a : dyn)) (record (a : 'x'))) (let s : (record (a : int)) r)",
+            "cast failed at r in main: (record (a : 'x')) is not (record (a : int))",
+        );
+        should_fail(
+            "(include std/prelude.sexp) (let v : (vec dyn) (vec 1 'a')) (let w : (vec int) v)",
+            "cast failed at v in main: (vec 1 'a') is not (vec int)",
+        );
+        should_eval(
+            "(let v : (vec dyn) (vec 1 2)) (let w : (vec int) v) w",
+            "(vec 1 2)",
+        )?;
+        should_eval(
+            "(let d : dyn (vec 1 2)) (map (fn x (+ x 1)) d)",
+            "(vec 2 3)",
+        )?;
+        should_fail(
+            "(include std/prelude.sexp) (let d : dyn 'a') (map (fn x (+ x 1)) d)",
+            "cast failed at d in main: 'a' is not (vec int)",
+        );
+        should_fail(
+            "(include std/prelude.sexp) (let d : dyn 'a') (map (fn x x) d)",
+            "cast failed at d in main: 'a' is not (vec dyn)",
+        );
+        should_eval("(let r : dyn (record (a : 1))) ([] r :a)", "1")?;
+        should_eval("(let d : dyn true) (case (d => 1) (true => 2))", "1")?;
+        should_eval("(let d : dyn false) (case (d => 1) (true => 2))", "2")?;
+        should_fail(
+            "(include std/prelude.sexp) (let d : dyn 1) (case (d => 1) (true => 2))",
+            "cast failed at d in main: 1 is not bool",
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn the_cast() -> Result<()> {
+        should_eval("(the int (+ 1 2))", "3")?;
+        should_eval("(cast int 1)", "1")?;
+        should_eval("(cast int 'a')", ":invalid-cast")?;
+        should_eval("(cast (vec int) (vec 1 2))", "(vec 1 2)")?;
+        should_eval("(cast (vec int) (vec 1 'a'))", ":invalid-cast")?;
+        should_eval(
+            "(cast (record (a : str)) (record (a : 'x')))",
+            "(record (a : 'x'))",
+        )?;
+        should_eval(
+            "(cast (record (a : str)) (record (a : 'x') (b : 1)))",
+            ":invalid-cast",
+        )?;
+        should_eval("(cast ((int) -> int) (fn x x))", "(fn x x)")?;
+        should_eval("(let d : dyn 'a') (cast str d)", "'a'")?;
+        should_fail(
+            "(include std/prelude.sexp) (let d : dyn 'a') (the int d)",
+            "cast failed at d in main: 'a' is not int",
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn prelude() -> Result<()> {
+        should_eval("(id 1)", "1")?;
+        should_eval("(dbg 1)", "1")?;
+        // qualified names of the std modules are not re-exported
+        should_fail(
+            "(include std/prelude.sexp) (int.+ 1 2)",
+            "unbound variable int.+",
+        );
+        should_fail(
+            "(include std/prelude.sexp) (let n 1) (map ?f (vec n))",
+            "hole ?f : ((int) -> a), fits: abs dbg id to-float to_string",
+        );
+        Ok(())
+    }
+}
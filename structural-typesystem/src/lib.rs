@@ -1,4 +1,3 @@
-pub mod infer;
 pub mod issuer;
 pub mod subtyping;
 pub mod type_alloc;
@@ -6,6 +5,7 @@ pub mod type_check;
 pub mod type_env;
 pub mod type_eval;
 pub mod types;
+pub mod unify;
 
 #[cfg(test)]
 pub(crate) mod tests {
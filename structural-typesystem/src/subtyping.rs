@@ -1,57 +1,116 @@
 use crate::{
-    type_env::TypeEnv,
+    type_env::{is_float_literal, is_int_literal, is_str_literal, TypeEnv},
     type_eval::type_eval,
-    types::{Id, Type},
+    types::{Id, Type, DYN_TYPE_KEYWORD},
 };
 use anyhow::Result;
-use std::collections::{BTreeMap, HashSet};
-use symbolic_expressions::parser::parse_str;
+use std::collections::{BTreeMap, BTreeSet};
 
 impl TypeEnv {
     fn is_subtype_vec(&mut self, a: Vec<Id>, b: Vec<Id>) -> Result<bool> {
-        Ok(a.len() == b.len()
-            && a.iter()
-                .zip(b.iter())
-                .map(|(ae, be)| self.is_subtype(*ae, *be))
-                .collect::<Result<Vec<_>>>()?
-                .iter()
-                .all(|e| *e))
+        if a.len() != b.len() {
+            return Ok(false);
+        }
+        for (ae, be) in a.into_iter().zip(b) {
+            if !self.is_subtype(ae, be)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
     }
 
     fn is_subtype_map(&mut self, a: BTreeMap<String, Id>, b: BTreeMap<String, Id>) -> Result<bool> {
-        let a_keys = a.keys().collect::<HashSet<_>>();
-        let b_keys = b.keys().collect::<HashSet<_>>();
-        Ok(a_keys == b_keys
-            && a_keys.into_iter().all(|k| {
-                self.is_subtype(*a.get(k).unwrap(), *b.get(k).unwrap())
-                    .unwrap_or(false)
-            }))
+        if !a.keys().eq(b.keys()) {
+            return Ok(false);
+        }
+        self.is_subtype_vec(a.into_values().collect(), b.into_values().collect())
+    }
+
+    /// `a` is subtype of any of `types`
+    fn is_subtype_any(&mut self, a: Id, types: &BTreeSet<Id>) -> Result<bool> {
+        for t in types {
+            if self.is_subtype(a, *t)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
     }
 
-    /// subtyping order for [TypeExpr]
+    /// subtyping order for [TypeExpr].
+    /// results for types without free variables are memoized. pairs met again while being compared
+    /// are assumed to be subtypes, so that recursive types are compared coinductively.
     pub fn is_subtype(&mut self, a: Id, b: Id) -> Result<bool> {
         if a == b {
             return Ok(true);
         }
+        if let Some(res) = self.subtype_memo.get(&(a, b)) {
+            return Ok(*res);
+        }
+        if self.subtype_assumptions.contains(&(a, b)) {
+            return Ok(true);
+        }
+        self.subtype_assumptions.insert((a, b));
+        let res = self.is_subtype_uncached(a, b);
+        self.subtype_assumptions.remove(&(a, b));
+        let res = res?;
+        // `true` may rely on assumptions of the enclosing comparisons, which may turn out to be false
+        let memoizable = !res || self.subtype_assumptions.is_empty();
+        if memoizable && !self.alloc.has_free_variables(a)? && !self.alloc.has_free_variables(b)? {
+            self.subtype_memo.insert((a, b), res);
+        }
+        Ok(res)
+    }
 
-        let any = self.get(&parse_str("any")?)?;
+    fn is_subtype_uncached(&mut self, a: Id, b: Id) -> Result<bool> {
+        let any = self.primitive("any")?;
+        let dyn_ty = self.primitive(DYN_TYPE_KEYWORD)?;
         let (a, b) = (type_eval(self, a)?, type_eval(self, b)?);
+        if a == b {
+            return Ok(true);
+        }
         let (a_ty, b_ty) = (self.alloc.get(a)?, self.alloc.get(b)?);
         let res = match (a_ty, b_ty) {
+            // `dyn` is consistent with every type. the interpreter checks casts from it
+            (Type::Primitive { id, .. }, _) | (_, Type::Primitive { id, .. }) if id == dyn_ty => {
+                Ok(true)
+            }
             // both are union types
             (Type::Union { types: a_types, .. }, Type::Union { types: b_types, .. }) => {
-                let is_subset = a_types.is_subset(&b_types);
-                let has_any_subtype = b_types.iter().any(|bt| {
-                    a_types
-                        .iter()
-                        .all(|at| self.is_subtype(*at, *bt).unwrap_or(false))
-                });
-                Ok(is_subset || has_any_subtype)
+                // each member of `a` is subtype of some member of `b`
+                for at in a_types {
+                    if !self.is_subtype_any(at, &b_types)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
             }
             // union types
-            (_, Type::Union { types, .. }) => Ok(types
-                .iter()
-                .any(|t| self.is_subtype(a, *t).unwrap_or(false))),
+            (_, Type::Union { types, .. }) => self.is_subtype_any(a, &types),
+            (Type::Union { types, .. }, _) => {
+                for t in types {
+                    if !self.is_subtype(t, b)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            // opaque types are related to their representation inside the defining module
+            (
+                Type::Nominal {
+                    repr,
+                    module: Some(module),
+                    ..
+                },
+                _,
+            ) if self.module() == Some(module.as_str()) => self.is_subtype(repr, b),
+            (
+                _,
+                Type::Nominal {
+                    repr,
+                    module: Some(module),
+                    ..
+                },
+            ) if self.module() == Some(module.as_str()) => self.is_subtype(a, repr),
             // fn types
             (
                 Type::Function {
@@ -76,44 +135,67 @@ impl TypeEnv {
             ) => self.is_subtype_map(a_fields, b_fields),
             (
                 Type::Container {
+                    id: a_con,
                     elements: a_elements,
-                    ..
                 },
                 Type::Container {
+                    id: b_con,
                     elements: b_elements,
-                    ..
                 },
-            ) => self.is_subtype_vec(a_elements, b_elements),
+            ) => Ok(a_con == b_con && self.is_subtype_vec(a_elements, b_elements)?),
             (Type::Variable { id: a_id, .. }, Type::Variable { id: b_id, .. }) => Ok(a_id == b_id),
+            // a bounded type variable is a subtype of supertypes of its bound
+            (
+                Type::Variable {
+                    upper_bound: Some(bound),
+                    ..
+                },
+                _,
+            ) => self.is_subtype(bound, b),
             // ? vs any
             (_, Type::Primitive { id, .. }) if id == any => Ok(true),
             // atom literal types
             (Type::Primitive { name, .. }, _) if name.starts_with(':') => {
-                let atom = self.get(&parse_str("atom")?)?;
+                let atom = self.primitive("atom")?;
                 self.is_subtype(atom, b)
             }
             // int literal types
-            (Type::Primitive { name, .. }, _) if name.parse::<i32>().is_ok() => {
-                let int = self.get(&parse_str("int")?)?;
+            (Type::Primitive { name, .. }, _) if is_int_literal(&name) => {
+                let int = self.primitive("int")?;
                 self.is_subtype(int, b)
             }
+            // float literal types
+            (Type::Primitive { name, .. }, _) if is_float_literal(&name) => {
+                let float = self.primitive("float")?;
+                self.is_subtype(float, b)
+            }
+            // numeric types
+            (Type::Primitive { name, .. }, _) if name == "int" || name == "float" => {
+                let num = self.primitive("num")?;
+                self.is_subtype(num, b)
+            }
             // str literal types
-            (Type::Primitive { name, .. }, _) if name.starts_with('\'') && name.ends_with('\'') => {
-                let str = self.get(&parse_str("str")?)?;
+            (Type::Primitive { name, .. }, _) if is_str_literal(&name) => {
+                let str = self.primitive("str")?;
                 self.is_subtype(str, b)
             }
             // bool literal types
             (Type::Primitive { name, .. }, _) if name == "true" || name == "false" => {
-                let bool = self.get(&parse_str("bool")?)?;
+                let bool = self.primitive("bool")?;
                 self.is_subtype(bool, b)
             }
             _ => Ok(false),
         };
+        // recursive types have no finite name
+        let name = |env: &Self, id| {
+            env.type_name(id)
+                .map_or("<recursive>".to_string(), |t| t.to_string())
+        };
         log::debug!(
             "check {} #{} <: {} #{} = {:?}",
-            self.type_name(a)?,
+            name(self, a),
             a,
-            self.type_name(b)?,
+            name(self, b),
             b,
             res
         );
@@ -123,8 +205,13 @@ impl TypeEnv {
 
 #[cfg(test)]
 mod test {
-    use crate::type_env::TypeEnv;
+    use crate::{
+        tests::setup,
+        type_env::TypeEnv,
+        types::{Id, Type},
+    };
     use anyhow::Result;
+    use std::collections::BTreeMap;
     use symbolic_expressions::parser::parse_str;
 
     fn is_subtype(a: &str, b: &str) -> Result<bool> {
@@ -143,6 +230,16 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_is_subtype_dyn() -> Result<()> {
+        assert!(is_subtype("dyn", "int")?);
+        assert!(is_subtype("int", "dyn")?);
+        assert!(is_subtype("(record (a : dyn))", "(record (a : 1))")?);
+        assert!(is_subtype("((dyn) -> int)", "((str) -> dyn)")?);
+        assert!(!is_subtype("any", "int")?);
+        Ok(())
+    }
+
     #[test]
     fn test_is_subtype_literal() -> Result<()> {
         assert!(is_subtype(":ok", "atom")?);
@@ -151,6 +248,31 @@ mod test {
         assert!(!is_subtype(":hoge", "(| :ok :err)")?);
         assert!(is_subtype("3", "int")?);
         assert!(is_subtype("3", "any")?);
+        assert!(is_subtype("-3", "int")?);
+        assert!(is_subtype("123456789012345678901234567890", "int")?);
+        assert!(is_subtype("1.5", "float")?);
+        assert!(!is_subtype("1.5", "int")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_subtype_num() -> Result<()> {
+        assert!(is_subtype("int", "num")?);
+        assert!(is_subtype("float", "num")?);
+        assert!(is_subtype("3", "num")?);
+        assert!(is_subtype("num", "any")?);
+        assert!(!is_subtype("num", "int")?);
+        assert!(!is_subtype("int", "float")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_subtype_str_literal() -> Result<()> {
+        assert!(is_subtype("'a'", "str")?);
+        assert!(is_subtype("\"'a b'\"", "str")?);
+        assert!(is_subtype("'a'", "(| 'a' 'b')")?);
+        assert!(!is_subtype("'c'", "(| 'a' 'b')")?);
+        assert!(!is_subtype("str", "'a'")?);
         Ok(())
     }
 
@@ -169,6 +291,15 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_is_subtype_container() -> Result<()> {
+        assert!(is_subtype("(vec 1)", "(vec int)")?);
+        assert!(is_subtype("(dict str int)", "(dict str any)")?);
+        assert!(!is_subtype("(dict str int)", "(dict atom int)")?);
+        assert!(!is_subtype("(vec (vec int))", "(dict int int)")?);
+        Ok(())
+    }
+
     #[test]
     fn test_is_subtype_union() -> Result<()> {
         assert!(is_subtype("int", "(| int any)")?);
@@ -182,6 +313,57 @@ mod test {
         assert!(is_subtype("(| 1 2 3)", "(| (| 1 2) (| 3))")?);
         assert!(is_subtype("(| int bool)", "(| int bool any)")?);
         assert!(is_subtype("(| str)", "(| int bool any)")?);
+        assert!(is_subtype("(| :a :b)", "atom")?);
+        assert!(!is_subtype("(| :a 1)", "atom")?);
+        assert!(is_subtype("(| 1 'a')", "(| int str)")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_subtype_error() -> Result<()> {
+        let err = is_subtype("int", "(| str ([] (record (a : int)) :b))")
+            .err()
+            .map(|e| e.to_string());
+        assert_eq!(
+            err,
+            Some("failed to evaluate ([] (record (a : int)) :b)".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_subtype_memo() -> Result<()> {
+        let mut env = TypeEnv::default();
+        let a = env.new_type(&parse_str("(record (a : 1))")?)?;
+        let b = env.new_type(&parse_str("(record (a : int))")?)?;
+        assert!(env.is_subtype(a, b)?);
+        assert_eq!(env.subtype_memo.get(&(a, b)), Some(&true));
+        assert!(!env.is_subtype(b, a)?);
+        assert_eq!(env.subtype_memo.get(&(b, a)), Some(&false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_subtype_recursive() -> Result<()> {
+        // `(record (value : T) (next : <itself>))` meets the pair being compared again in `next`
+        fn stream(env: &mut TypeEnv, value: &str) -> Result<Id> {
+            let value = env.new_type_str(value)?;
+            let id = env.alloc.issue_id();
+            let fields = BTreeMap::from([("value".to_string(), value), ("next".to_string(), id)]);
+            env.alloc.insert(Type::record(id, fields));
+            Ok(id)
+        }
+        setup();
+        let mut env = TypeEnv::default();
+        let ints = stream(&mut env, "int")?;
+        let nums = stream(&mut env, "num")?;
+        let strs = stream(&mut env, "str")?;
+        assert!(env.is_subtype(ints, nums)?);
+        assert!(!env.is_subtype(nums, ints)?);
+        assert!(!env.is_subtype(ints, strs)?);
+        assert!(env.subtype_assumptions.is_empty());
+        // widening doesn't unfold them
+        assert_eq!(env.widen(ints)?, ints);
         Ok(())
     }
 }
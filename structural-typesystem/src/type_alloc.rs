@@ -3,6 +3,7 @@ use crate::{
     types::{Id, Type, TypeExpr},
 };
 use anyhow::{anyhow, Result};
+use std::collections::HashSet;
 use symbolic_expressions::Sexp;
 
 /// [TypeAlloc] is globally unique.
@@ -70,6 +71,9 @@ impl TypeAlloc {
                     .collect::<Result<Vec<_>>>()?
                     .join(" ")
             )),
+            Type::Nominal { id, name, repr, .. } => {
+                Ok(format!("{}_#{}({})", name, id, self.debug(repr)?))
+            }
             Type::Union { id, types } => Ok(format!(
                 "(|_#{} {})",
                 id,
@@ -89,6 +93,8 @@ impl TypeAlloc {
         match self.get(id)? {
             // primitive types
             Type::Primitive { name, .. } => Ok(Sexp::String(name)),
+            // nominal types are referred by name
+            Type::Nominal { name, .. } => Ok(Sexp::String(name)),
             // concrete types
             Type::Variable {
                 instance: Some(inst),
@@ -127,7 +133,7 @@ impl TypeAlloc {
                 let container = self.as_sexp(id)?;
                 let elements = elements
                     .iter()
-                    .map(|id| self.as_sexp(*id))
+                    .map(|id| self.as_sexp_rec(*id, issuer, nest + 1))
                     .collect::<Result<Vec<_>>>()?;
                 Ok(Sexp::List(
                     vec![container]
@@ -139,7 +145,7 @@ impl TypeAlloc {
             Type::Union { types, .. } => {
                 let types = types
                     .iter()
-                    .map(|id| self.as_sexp(*id))
+                    .map(|id| self.as_sexp_rec(*id, issuer, nest + 1))
                     .collect::<Result<Vec<_>>>()?;
                 Ok(Sexp::List(
                     vec![Sexp::String("|".to_string())]
@@ -174,10 +180,64 @@ impl TypeAlloc {
                 Ok(elements.iter().any(|id| self.is_generic(*id).unwrap()))
             }
             Type::Union { types, .. } => Ok(types.iter().any(|id| self.is_generic(*id).unwrap())),
-            Type::Primitive { .. } => Ok(false),
+            Type::Primitive { .. } | Type::Nominal { .. } => Ok(false),
             Type::Variable { .. } => Ok(true),
         }
     }
+
+    /// types directly referred to by `id`
+    fn children(&self, id: Id) -> Result<Vec<Id>> {
+        Ok(match self.get(id)? {
+            Type::Variable { instance, .. } => instance.into_iter().collect(),
+            Type::Function { args, ret, .. } => args.into_iter().chain([ret]).collect(),
+            Type::Record { fields, .. } => fields.into_values().collect(),
+            Type::Container { elements, .. } => elements,
+            Type::Union { types, .. } => types.into_iter().collect(),
+            Type::Primitive { .. } | Type::Nominal { .. } => vec![],
+        })
+    }
+
+    /// contains type variables without instance
+    pub fn has_free_variables(&self, id: Id) -> Result<bool> {
+        self.has_free_variables_rec(id, &mut HashSet::new())
+    }
+
+    fn has_free_variables_rec(&self, id: Id, visited: &mut HashSet<Id>) -> Result<bool> {
+        // types referred to again, like the fields of recursive types, are already checked
+        if !visited.insert(id) {
+            return Ok(false);
+        }
+        if let Type::Variable { instance: None, .. } = self.get(id)? {
+            return Ok(true);
+        }
+        for child in self.children(id)? {
+            if self.has_free_variables_rec(child, visited)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// refers to itself, like a record with a field of its own type.
+    /// such types have no surface syntax and are only built internally, by allocating a type that
+    /// refers to its own id
+    pub fn is_recursive(&self, id: Id) -> Result<bool> {
+        self.is_recursive_rec(id, &mut vec![])
+    }
+
+    fn is_recursive_rec(&self, id: Id, path: &mut Vec<Id>) -> Result<bool> {
+        if path.contains(&id) {
+            return Ok(true);
+        }
+        path.push(id);
+        for child in self.children(id)? {
+            if self.is_recursive_rec(child, path)? {
+                return Ok(true);
+            }
+        }
+        path.pop();
+        Ok(false)
+    }
 }
 
 #[cfg(test)]
@@ -1,100 +1,446 @@
 use crate::{
-    infer::InferType,
-    type_env::TypeEnv,
+    type_env::{str_literal, union, Hole, TypeEnv},
     type_eval::{ensure_subtype, type_eval},
-    types::{Id, Type},
+    types::{Id, Type, DICT_TYPE_KEYWORD, DYN_TYPE_KEYWORD, LIST_TYPE_KEYWORD},
+    unify::{fresh, fresh_with, prune, skolemize, unify},
 };
 use anyhow::Result;
-use ast::ast::{Case, Expr, FnApp, FnDef, Let, Program, TypeDef, Value};
-
-use std::collections::{BTreeMap, HashSet};
+use ast::ast::{
+    float_literal, Blame, Case, Cast, Expr, FnApp, FnDef, Import, ImportNames, Let, LetIn, Module,
+    Program, TypeDef, TypeDefKind, Value,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use symbolic_expressions::Sexp;
 
+/// bidirectional type checker.
+/// [TypeCheck::synth] infers the type of an expression and [TypeCheck::check] checks it against an expected type,
+/// which propagates annotations into lambdas and literals.
+/// expressions of type `dyn` checked against other types are wrapped in [Blame]s.
 pub trait TypeCheck {
-    fn type_check(&self, env: &mut TypeEnv) -> Result<Id>;
+    fn synth(&mut self, env: &mut TypeEnv) -> Result<Id>;
+
+    fn check(&mut self, env: &mut TypeEnv, expected: Id) -> Result<Id> {
+        subsume(self, env, expected)
+    }
+}
+
+/// synthesizes the type of `expr` and ensures it is a subtype of `expected`
+fn subsume<T: TypeCheck + ?Sized>(expr: &mut T, env: &mut TypeEnv, expected: Id) -> Result<Id> {
+    let ty = expr.synth(env)?;
+    unify(env, ty, expected)?;
+    Ok(expected)
+}
+
+/// like [subsume], but wraps `expr` whose type has `dyn` where `expected` is more precise
+/// in a [Blame] checking `expected` at runtime
+fn subsume_dyn(expr: &mut Expr, env: &mut TypeEnv, expected: Id) -> Result<Id> {
+    let ty = expr.synth(env)?;
+    unify(env, ty, expected)?;
+    cast_dyn(expr, env, ty, expected)?;
+    Ok(expected)
+}
+
+/// wraps `expr` of type `ty` in a [Blame] checking `expected` at runtime if it [needs_cast].
+/// type variables of `expected` which are still unresolved are checked as `dyn`
+fn cast_dyn(expr: &mut Expr, env: &mut TypeEnv, ty: Id, expected: Id) -> Result<()> {
+    let expected = prune(&mut env.alloc, expected);
+    let expected = type_eval(env, expected)?;
+    let expected = dyn_structure(env, expected)?;
+    if !needs_cast(env, ty, expected)? {
+        return Ok(());
+    }
+    let location = format!("{} in {}", expr, env.module().unwrap_or("main"));
+    let inner = std::mem::replace(expr, Expr::Hole(None));
+    *expr = Expr::Blame(Blame::new(
+        Box::new(inner),
+        env.type_name(expected)?,
+        location,
+        env.module().map(str::to_string),
+    ));
+    Ok(())
+}
+
+/// `id` with its free type variables replaced by `dyn`: `(vec a)` is `(vec dyn)`.
+/// unions with free variables are `dyn` as a whole
+fn dyn_structure(env: &mut TypeEnv, id: Id) -> Result<Id> {
+    let id = prune(&mut env.alloc, id);
+    if !env.alloc.has_free_variables(id)? {
+        return Ok(id);
+    }
+    let ty = match env.alloc.get(id)? {
+        Type::Function { args, ret, .. } => {
+            let args = args
+                .into_iter()
+                .map(|arg| dyn_structure(env, arg))
+                .collect::<Result<Vec<_>>>()?;
+            let ret = dyn_structure(env, ret)?;
+            Type::function(env.alloc.issue_id(), args, ret)
+        }
+        Type::Record { fields, .. } => {
+            let fields = fields
+                .into_iter()
+                .map(|(label, field)| Ok((label, dyn_structure(env, field)?)))
+                .collect::<Result<BTreeMap<_, _>>>()?;
+            Type::record(env.alloc.issue_id(), fields)
+        }
+        Type::Container { id: con, elements } => {
+            let elements = elements
+                .into_iter()
+                .map(|elem| dyn_structure(env, elem))
+                .collect::<Result<Vec<_>>>()?;
+            Type::container(con, elements)
+        }
+        _ => return env.primitive(DYN_TYPE_KEYWORD),
+    };
+    let id = env.alloc.issue_id();
+    env.alloc.insert(ty);
+    Ok(id)
+}
+
+/// `ty` has `dyn` at a position where `expected` is neither `dyn` nor `any`:
+/// `(record (a : dyn))` against `(record (a : int))`, `(vec dyn)` against `(vec int)`
+fn needs_cast(env: &mut TypeEnv, ty: Id, expected: Id) -> Result<bool> {
+    let ty = prune(&mut env.alloc, ty);
+    let expected = prune(&mut env.alloc, expected);
+    let expected = type_eval(env, expected)?;
+    if env.is_dyn(expected)? || expected == env.primitive("any")? {
+        return Ok(false);
+    }
+    if env.is_dyn(ty)? {
+        return Ok(true);
+    }
+    match (env.alloc.get(ty)?, env.alloc.get(expected)?) {
+        (Type::Union { types, .. }, _) => {
+            for member in types {
+                if needs_cast(env, member, expected)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        (
+            Type::Record { fields, .. },
+            Type::Record {
+                fields: expected, ..
+            },
+        ) => {
+            for (label, field) in fields {
+                match expected.get(&label) {
+                    Some(&expected) if needs_cast(env, field, expected)? => return Ok(true),
+                    _ => {}
+                }
+            }
+            Ok(false)
+        }
+        (
+            Type::Container { id, elements },
+            Type::Container {
+                id: expected_id,
+                elements: expected,
+            },
+        ) if id == expected_id => {
+            for (element, expected) in elements.into_iter().zip(expected) {
+                if needs_cast(env, element, expected)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        // arguments are checked by the function itself
+        (Type::Function { ret, .. }, Type::Function { ret: expected, .. }) => {
+            needs_cast(env, ret, expected)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// resolves instances and type operators of an expected type
+fn resolve(env: &mut TypeEnv, expected: Id) -> Result<Type> {
+    let expected = prune(&mut env.alloc, expected);
+    let expected = type_eval(env, expected)?;
+    env.alloc.get(expected)
+}
+
+/// maximum number of literals of a base type kept in the type of `case`
+const MAX_LITERAL_UNION: usize = 8;
+
+fn new_variable(env: &mut TypeEnv) -> Id {
+    let id = env.alloc.issue_id();
+    env.alloc.insert(Type::variable(id, None));
+    id
+}
+
+/// least upper bound of `a` and `b`: the wider one if they are related, otherwise their union.
+/// types with free variables are unified instead, as subtyping can not instantiate them.
+fn join(env: &mut TypeEnv, a: Id, b: Id) -> Result<Id> {
+    if env.alloc.has_free_variables(a)? || env.alloc.has_free_variables(b)? {
+        unify(env, b, a)?;
+        return Ok(a);
+    }
+    if env.is_subtype(b, a)? {
+        return Ok(a);
+    }
+    if env.is_subtype(a, b)? {
+        return Ok(b);
+    }
+    // records with the same labels are joined field-wise: `(record (a : int))` and
+    // `(record (a : str))` is `(record (a : (| int str)))`
+    let (a, b) = (prune(&mut env.alloc, a), prune(&mut env.alloc, b));
+    if let (
+        Type::Record {
+            fields: a_fields, ..
+        },
+        Type::Record {
+            fields: b_fields, ..
+        },
+    ) = (env.alloc.get(a)?, env.alloc.get(b)?)
+    {
+        if a_fields.keys().eq(b_fields.keys()) {
+            let fields = a_fields
+                .into_iter()
+                .zip(b_fields.into_values())
+                .map(|((label, a_field), b_field)| Ok((label, join(env, a_field, b_field)?)))
+                .collect::<Result<BTreeMap<_, _>>>()?;
+            let id = env.alloc.issue_id();
+            env.alloc.insert(Type::record(id, fields));
+            return Ok(id);
+        }
+    }
+    let mut members = BTreeSet::new();
+    for ty in [a, b] {
+        match env.alloc.get(ty)? {
+            Type::Union { types, .. } => members.extend(types),
+            _ => {
+                members.insert(ty);
+            }
+        }
+    }
+    // drop members subsumed by another one: `(| int str)` joined with `num` is `(| str num)`
+    let mut types = BTreeSet::new();
+    for &ty in &members {
+        let mut subsumed = false;
+        for &other in &members {
+            if other != ty && env.is_subtype(ty, other)? {
+                subsumed = true;
+                break;
+            }
+        }
+        if !subsumed {
+            types.insert(ty);
+        }
+    }
+    let id = env.alloc.issue_id();
+    env.alloc.insert(Type::Union { id, types });
+    Ok(id)
+}
+
+/// keys of dicts are strings or atoms
+fn dict_key(env: &mut TypeEnv) -> Result<Id> {
+    env.new_type_str("(| str atom)")
 }
 
 impl TypeCheck for Value {
-    fn type_check(&self, env: &mut TypeEnv) -> Result<Id> {
+    fn synth(&mut self, env: &mut TypeEnv) -> Result<Id> {
         match self {
+            Value::External(name) => Err(anyhow::anyhow!(
+                "external {} requires type annotation",
+                name
+            )),
+            Value::Bool(v) => env.new_type_str(if *v { "true" } else { "false" }),
+            Value::Number(v) => env.new_type_str(v.to_string().as_str()),
+            Value::Float(v) => env.new_type_str(&float_literal(*v)),
+            Value::Atom(v) => env.new_type_str(format!(":{}", v).as_str()),
+            // not parsed by `new_type_str` as the contents may contain parentheses or spaces
+            Value::String(v) => env.new_type(&Sexp::String(str_literal(v))),
             Value::Record(fields) => {
                 let field_tys = fields
-                    .iter()
-                    .map(|(name, expr)| expr.type_check(env).map(|id| (name.to_string(), id)))
+                    .iter_mut()
+                    .map(|(name, expr)| expr.synth(env).map(|id| (name.to_string(), id)))
                     .collect::<Result<BTreeMap<_, _>>>()?;
                 let id = env.alloc.issue_id();
-                let record_ty = Type::record(id, field_tys);
-                env.alloc.insert(record_ty);
+                env.alloc.insert(Type::record(id, field_tys));
                 Ok(id)
             }
             Value::List(elems) => {
-                let vec_ty = env.new_type_str("vec")?;
-                let elem_tys = elems
-                    .iter()
-                    .map(|elem| elem.type_check(env))
-                    .collect::<Result<Vec<_>>>()?;
-                if elem_tys.iter().collect::<HashSet<_>>().len() != 1 {
-                    return Err(anyhow::anyhow!(
-                        "list elements must have same type: [{}]",
-                        elem_tys
-                            .iter()
-                            .map(|id| env.type_name(*id).map(|t| t.to_string()))
-                            .collect::<Result<Vec<_>>>()?
-                            .join(", ")
-                    ));
+                let vec_ty = env.new_type_str(LIST_TYPE_KEYWORD)?;
+                // the element type is the join of the widened element types:
+                // `(vec 1 2)` is `(vec int)` and `(vec 1 'a')` is `(vec (| int str))`.
+                // if empty, element type is a type variable.
+                let mut elem_ty = None;
+                for elem in elems {
+                    let ty = elem.synth(env)?;
+                    let ty = env.widen(ty)?;
+                    elem_ty = Some(match elem_ty {
+                        Some(acc) => join(env, acc, ty)?,
+                        None => ty,
+                    });
                 }
-                let elem_ty = elem_tys[0];
-                let container_ty = Type::container(vec_ty, vec![elem_ty]);
+                let elem_ty = match elem_ty {
+                    Some(ty) => ty,
+                    None => new_variable(env),
+                };
                 let id = env.alloc.issue_id();
-                env.alloc.insert(container_ty);
+                env.alloc.insert(Type::container(vec_ty, vec![elem_ty]));
+                Ok(id)
+            }
+            Value::Dict(entries) => {
+                let dict_ty = env.new_type_str(DICT_TYPE_KEYWORD)?;
+                let key_bound = dict_key(env)?;
+                // the 1st entry decides the key and value types. they are widened so that
+                // other keys and values can be inserted. if empty, both are type variables.
+                let (key_ty, value_ty) = match entries.split_first_mut() {
+                    Some(((key, value), rest)) => {
+                        let key_ty = key.synth(env)?;
+                        let key_ty = env.widen(key_ty)?;
+                        ensure_subtype(env, key_ty, key_bound)?;
+                        let value_ty = value.synth(env)?;
+                        let value_ty = env.widen(value_ty)?;
+                        for (key, value) in rest {
+                            key.check(env, key_ty)?;
+                            value.check(env, value_ty)?;
+                        }
+                        (key_ty, value_ty)
+                    }
+                    None => {
+                        let key_ty = env.alloc.issue_id();
+                        env.alloc.insert(Type::variable(key_ty, Some(key_bound)));
+                        (key_ty, new_variable(env))
+                    }
+                };
+                let id = env.alloc.issue_id();
+                env.alloc
+                    .insert(Type::container(dict_ty, vec![key_ty, value_ty]));
                 Ok(id)
             }
-            _ => self.infer_type(env, &Default::default()),
+        }
+    }
+
+    fn check(&mut self, env: &mut TypeEnv, expected: Id) -> Result<Id> {
+        let vec_ty = env.new_type_str(LIST_TYPE_KEYWORD)?;
+        let dict_ty = env.new_type_str(DICT_TYPE_KEYWORD)?;
+        match (&mut *self, resolve(env, expected)?) {
+            (
+                Value::Record(fields),
+                Type::Record {
+                    fields: expected_fields,
+                    ..
+                },
+            ) if fields.len() == expected_fields.len()
+                && fields.keys().all(|k| expected_fields.contains_key(k)) =>
+            {
+                for (name, expr) in fields {
+                    expr.check(env, expected_fields[name])?;
+                }
+                Ok(expected)
+            }
+            (Value::List(elems), Type::Container { id, elements })
+                if id == vec_ty && elements.len() == 1 =>
+            {
+                for elem in elems {
+                    elem.check(env, elements[0])?;
+                }
+                Ok(expected)
+            }
+            (Value::Dict(entries), Type::Container { id, elements })
+                if id == dict_ty && elements.len() == 2 =>
+            {
+                let key_bound = dict_key(env)?;
+                if !env.alloc.has_free_variables(elements[0])? {
+                    ensure_subtype(env, elements[0], key_bound)?;
+                }
+                for (key, value) in entries {
+                    key.check(env, elements[0])?;
+                    value.check(env, elements[1])?;
+                }
+                Ok(expected)
+            }
+            _ => subsume(self, env, expected),
         }
     }
 }
 
-impl TypeCheck for FnDef {
-    fn type_check(&self, env: &mut TypeEnv) -> Result<Id> {
-        let arg_tys = self
+/// parameters without annotation take `expected` argument types if given.
+/// type variables of annotations are instantiated per definition, so `(fn (x : a) (+ x 1))` doesn't bind `a` of others
+fn type_fn(def: &mut FnDef, env: &mut TypeEnv, expected: Option<(Vec<Id>, Id)>) -> Result<Id> {
+    let mut mappings = HashMap::new();
+    env.scoped(|env| {
+        let arg_tys = def
             .args
             .iter()
-            .map(|arg| {
-                let arg_ty = if let Some(arg_ty) = &arg.typ {
-                    env.new_type(arg_ty)?
-                } else {
-                    let id = env.alloc.issue_id();
-                    env.alloc.insert(Type::variable(id, None));
-                    id
+            .enumerate()
+            .map(|(i, arg)| {
+                let expected_arg = expected.as_ref().map(|(args, _)| args[i]);
+                let arg_ty = match (&arg.typ, expected_arg) {
+                    (Some(typ), Some(expected_arg)) => {
+                        let arg_ty = env.new_declared_type(typ)?;
+                        let arg_ty = fresh_with(env, arg_ty, &mut mappings);
+                        // parameters are contravariant
+                        unify(env, expected_arg, arg_ty)?;
+                        arg_ty
+                    }
+                    (Some(typ), None) => {
+                        let arg_ty = env.new_declared_type(typ)?;
+                        fresh_with(env, arg_ty, &mut mappings)
+                    }
+                    (None, Some(expected_arg)) => expected_arg,
+                    (None, None) => new_variable(env),
                 };
                 env.set_variable(&arg.name, arg_ty);
                 Ok(arg_ty)
             })
             .collect::<Result<Vec<_>>>()?;
-        let ret_ty = self.body.type_check(env)?;
+        let non_generic = env.non_generic.len();
+        env.non_generic.extend(arg_tys.iter());
+        let ret_ty = match &expected {
+            Some((_, expected_ret)) => def.body.check(env, *expected_ret),
+            None => def.body.synth(env),
+        };
+        env.non_generic.truncate(non_generic);
+        let ret_ty = ret_ty?;
         let fn_ty = env.alloc.issue_id();
         env.alloc.insert(Type::function(fn_ty, arg_tys, ret_ty));
         Ok(fn_ty)
+    })
+}
+
+impl TypeCheck for FnDef {
+    fn synth(&mut self, env: &mut TypeEnv) -> Result<Id> {
+        type_fn(self, env, None)
+    }
+
+    fn check(&mut self, env: &mut TypeEnv, expected: Id) -> Result<Id> {
+        match resolve(env, expected)? {
+            Type::Function { args, ret, .. } if args.len() == self.args.len() => {
+                type_fn(self, env, Some((args, ret)))
+            }
+            _ => subsume(self, env, expected),
+        }
     }
 }
 
 impl TypeCheck for Let {
-    fn type_check(&self, env: &mut TypeEnv) -> Result<Id> {
+    fn synth(&mut self, env: &mut TypeEnv) -> Result<Id> {
         log::debug!("let {} = {}", self.name, self.value);
-        let use_decl_type = matches!(self.value.as_ref(), Expr::Literal(Value::External(_)));
-
-        let let_ty = if self.typ.is_some() || use_decl_type {
-            let decl_ty = self.typ.as_ref().unwrap();
-            let decl_ty = env.new_type(decl_ty)?;
-            let decl_ty = type_eval(env, decl_ty)?;
-            if !use_decl_type {
-                let value_ty = self.value.type_check(env)?;
-                ensure_subtype(env, value_ty, decl_ty)?;
+        let let_ty = match (&self.typ, self.value.as_mut()) {
+            (Some(typ), Expr::Literal(Value::External(_))) => {
+                let decl_ty = env.new_declared_type(typ)?;
+                type_eval(env, decl_ty)?
             }
-            decl_ty
-        } else {
-            let infer_ty = self.value.infer_type(env, &HashSet::new())?;
-            log::debug!("infer {} : {}", self.name, env.type_name(infer_ty)?);
-            infer_ty
+            (Some(typ), value) => {
+                let decl_ty = env.new_declared_type(typ)?;
+                let decl_ty = type_eval(env, decl_ty)?;
+                // `(let f : ((a) -> a) (fn x 1))` is rejected as `a` may be any type
+                let rigid_ty = skolemize(env, decl_ty);
+                value.check(env, rigid_ty)?;
+                decl_ty
+            }
+            // `(let x 1)` is `int` so that `x` can be used where other ints are expected.
+            // `(let x : const 1)` keeps `1`
+            (None, value) if self.widen => {
+                let ty = value.synth(env)?;
+                env.widen(ty)?
+            }
+            (None, value) => value.synth(env)?,
         };
         env.set_variable(&self.name, let_ty);
         log::debug!("{} : {}", self.name, env.type_name(let_ty)?);
@@ -102,87 +448,455 @@ impl TypeCheck for Let {
     }
 }
 
+impl TypeCheck for LetIn {
+    fn synth(&mut self, env: &mut TypeEnv) -> Result<Id> {
+        env.scoped(|env| {
+            for binding in &mut self.bindings {
+                binding.synth(env)?;
+            }
+            self.body.synth(env)
+        })
+    }
+
+    fn check(&mut self, env: &mut TypeEnv, expected: Id) -> Result<Id> {
+        env.scoped(|env| {
+            for binding in &mut self.bindings {
+                binding.synth(env)?;
+            }
+            self.body.check(env, expected)
+        })
+    }
+}
+
+/// `(do e1 ... en)` has the type of `en`. bindings by `let` are visible until the end of the block.
+fn type_do(exprs: &mut [Expr], env: &mut TypeEnv, expected: Option<Id>) -> Result<Id> {
+    let Some((last, init)) = exprs.split_last_mut() else {
+        return Err(anyhow::anyhow!("do must have at least one expression"));
+    };
+    env.scoped(|env| {
+        for expr in init {
+            expr.synth(env)?;
+        }
+        match expected {
+            Some(expected) => last.check(env, expected),
+            None => last.synth(env),
+        }
+    })
+}
+
 impl TypeCheck for FnApp {
     /// f :: a -> b
     /// v :: a
-    fn type_check(&self, env: &mut TypeEnv) -> Result<Id> {
-        self.infer_type(env, &HashSet::new())?;
-        let f_ty = self.0.type_check(env)?;
-        let Type::Function { args, ret, .. } = env.alloc.get(f_ty)? else {
-            return Err(anyhow::anyhow!("{} is not appliable type", self.0));
+    fn synth(&mut self, env: &mut TypeEnv) -> Result<Id> {
+        let FnApp(f, values) = self;
+        let f_ty = f.synth(env)?;
+        let f_ty = prune(&mut env.alloc, f_ty);
+        let (args, ret) = match env.alloc.get(f_ty)? {
+            Type::Function { args, ret, .. } => (args, ret),
+            // applying a function typed parameter
+            Type::Variable { instance: None, .. } => {
+                let args = values.iter().map(|_| new_variable(env)).collect::<Vec<_>>();
+                let ret = new_variable(env);
+                let fn_ty = env.alloc.issue_id();
+                env.alloc.insert(Type::function(fn_ty, args.clone(), ret));
+                unify(env, fn_ty, f_ty)?;
+                (args, ret)
+            }
+            // arguments of a `dyn` function are `dyn`, and so is the result
+            _ if env.is_dyn(f_ty)? => {
+                for value in values {
+                    value.synth(env)?;
+                }
+                return Ok(f_ty);
+            }
+            _ => return Err(anyhow::anyhow!("{} is not appliable type", f)),
         };
-        for (value, arg) in self.1.iter().zip(args.iter()) {
-            let param_ty = value.type_check(env)?;
-            // if `arg_ty` is generic, skip subtype check
+        anyhow::ensure!(
+            args.len() == values.len(),
+            "{} expects {} arguments but {} given",
+            f,
+            args.len(),
+            values.len()
+        );
+        // type variables returned by function typed parameters, like the accumulator of `fold`,
+        // are bound to widened types so that the function can return other values of the type
+        let returned = args
+            .iter()
+            .filter_map(|arg| {
+                let arg = prune(&mut env.alloc, *arg);
+                match env.alloc.get(arg) {
+                    Ok(Type::Function { ret, .. }) => Some(prune(&mut env.alloc, ret)),
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        // lambdas are checked last so that their parameters take types decided by other arguments.
+        // arguments of bare type variables, like the element of `cons`, are checked after the ones
+        // of structured types so that `a` is bound to `int` by `(vec int)` rather than to `0`
+        let mut pairs = values
+            .iter_mut()
+            .zip(args.iter())
+            .map(|(value, arg)| {
+                let pruned = prune(&mut env.alloc, *arg);
+                let is_var = matches!(env.alloc.get(pruned), Ok(Type::Variable { .. }));
+                let key = (matches!(value.as_ref(), Expr::FnDef(_)), is_var);
+                (key, value, arg)
+            })
+            .collect::<Vec<_>>();
+        pairs.sort_by_key(|(key, _, _)| *key);
+        // `dyn` arguments of generic parameters are cast once the other arguments resolve them:
+        // `d` of `(map (fn x (+ x 1)) d)` is checked to be `(vec int)`
+        let mut generic = vec![];
+        for (_, value, arg) in pairs {
+            let arg = prune(&mut env.alloc, *arg);
+            let is_fn = matches!(env.alloc.get(arg)?, Type::Function { .. });
+            if is_fn || !env.alloc.has_free_variables(arg)? {
+                value.check(env, arg)?;
+            } else {
+                let mut value_ty = value.synth(env)?;
+                if returned.contains(&arg) {
+                    value_ty = env.widen(value_ty)?;
+                }
+                unify(env, value_ty, arg)?;
+                generic.push((value, value_ty, arg));
+            }
+        }
+        for (value, value_ty, arg) in generic {
+            cast_dyn(value, env, value_ty, arg)?;
+        }
+        // bounds which refer to other type variables are checked after all arguments are unified
+        for arg in args {
             if let Type::Variable {
                 upper_bound: Some(bound),
                 ..
-            } = env.alloc.get(*arg)?
+            } = env.alloc.get(arg)?
             {
-                ensure_subtype(env, *arg, bound)?;
-            }
-            if !env.alloc.is_generic(*arg)? {
-                ensure_subtype(env, param_ty, *arg)?;
+                if !env.alloc.has_free_variables(arg)? {
+                    ensure_subtype(env, arg, bound)?;
+                }
             }
         }
-        Ok(ret)
+        let ret = prune(&mut env.alloc, ret);
+        type_eval(env, ret)
     }
 }
 
 impl TypeCheck for TypeDef {
-    fn type_check(&self, env: &mut TypeEnv) -> Result<Id> {
-        let id = env.new_type(&self.typ)?;
+    fn synth(&mut self, env: &mut TypeEnv) -> Result<Id> {
+        let id = env.new_declared_type(&self.typ)?;
         let id = type_eval(env, id)?;
-        env.new_alias(&self.name, id);
-        Ok(id)
+        match self.kind {
+            TypeDefKind::Alias => {
+                env.new_alias(&self.name, id);
+                Ok(id)
+            }
+            TypeDefKind::NewType => {
+                let nominal = env.new_nominal(&self.name, id);
+                let wrap = env.alloc.issue_id();
+                env.alloc.insert(Type::function(wrap, vec![id], nominal));
+                env.set_variable(&self.wrap_fn(), wrap);
+                let unwrap = env.alloc.issue_id();
+                env.alloc.insert(Type::function(unwrap, vec![nominal], id));
+                env.set_variable(&self.unwrap_fn(), unwrap);
+                Ok(nominal)
+            }
+            TypeDefKind::Opaque => Ok(env.new_opaque(&self.name, id)),
+        }
     }
 }
 
+/// checks that patterns are bool and returns whether each branch is reachable.
+/// a branch is dead if its pattern is `false` or a preceding pattern is `true`.
+fn check_patterns(case: &mut Case, env: &mut TypeEnv) -> Result<Vec<bool>> {
+    let bool_ty = env.primitive("bool")?;
+    let mut exhausted = false;
+    let mut live = vec![];
+    for (pattern, _) in &mut case.branches {
+        let pattern_ty = pattern.synth(env)?;
+        if !env.is_subtype(pattern_ty, bool_ty)? {
+            return Err(anyhow::anyhow!(
+                "pattern {} must be bool but {}",
+                pattern,
+                env.type_name(pattern_ty)?
+            ));
+        }
+        // `dyn` guards are consistent with both `true` and `false`, so they are cast to
+        // `bool` and decided at runtime
+        if env.is_dyn(pattern_ty)? {
+            pattern.check(env, bool_ty)?;
+        }
+        let guard = static_guard(env, pattern_ty)?;
+        let reachable = !exhausted && guard != Some(false);
+        if !reachable {
+            log::debug!("case branch {} is unreachable", pattern);
+        }
+        live.push(reachable);
+        exhausted |= guard == Some(true);
+    }
+    Ok(live)
+}
+
+/// the value of a guard whose type is literally `true` or `false`, or a union of only one of them
+fn static_guard(env: &mut TypeEnv, ty: Id) -> Result<Option<bool>> {
+    let ty = prune(&mut env.alloc, ty);
+    let ty = type_eval(env, ty)?;
+    let members = match env.alloc.get(ty)? {
+        Type::Union { types, .. } => types.into_iter().collect(),
+        _ => vec![ty],
+    };
+    let mut value = None;
+    for member in members {
+        let literal = match env.alloc.get(member)? {
+            Type::Primitive { name, .. } if name == "true" => true,
+            Type::Primitive { name, .. } if name == "false" => false,
+            _ => return Ok(None),
+        };
+        if value.is_some_and(|value| value != literal) {
+            return Ok(None);
+        }
+        value = Some(literal);
+    }
+    Ok(value)
+}
+
+/// widens the literals of a base type in union `id` if there are more than [MAX_LITERAL_UNION] of them.
+/// the others are kept, so that tags like `:none` stay distinguishable
+fn bound_literals(env: &mut TypeEnv, id: Id) -> Result<Id> {
+    let id = prune(&mut env.alloc, id);
+    let Type::Union { types, .. } = env.alloc.get(id)? else {
+        return Ok(id);
+    };
+    let mut literals = BTreeMap::<Id, usize>::new();
+    for &ty in &types {
+        let base = env.widen(ty)?;
+        if base != ty {
+            *literals.entry(base).or_default() += 1;
+        }
+    }
+    if literals.values().all(|&n| n <= MAX_LITERAL_UNION) {
+        return Ok(id);
+    }
+    let mut ret_ty = None;
+    for ty in types {
+        let base = env.widen(ty)?;
+        let ty = if literals.get(&base).is_some_and(|&n| n > MAX_LITERAL_UNION) {
+            base
+        } else {
+            ty
+        };
+        ret_ty = Some(match ret_ty {
+            Some(acc) => join(env, acc, ty)?,
+            None => ty,
+        });
+    }
+    Ok(ret_ty.unwrap_or(id))
+}
+
+/// names the branch of `case` whose body is incompatible
+fn branch_error(pattern: &Expr, e: anyhow::Error) -> anyhow::Error {
+    anyhow::anyhow!("case branch {}: {}", pattern, e)
+}
+
 impl TypeCheck for Case {
-    fn type_check(&self, env: &mut TypeEnv) -> Result<Id> {
-        let body_tys = self
-            .branches
-            .iter()
-            .map(|(pattern, body)| {
-                let pattern_ty = pattern.type_check(env)?;
-                if pattern_ty != env.new_type_str("bool")? {
-                    return Err(anyhow::anyhow!(
-                        "pattern {} must be bool but {}",
-                        pattern,
-                        env.type_name(pattern_ty)?
-                    ));
+    /// unreachable branches are type checked but don't contribute to the type of `case`
+    fn synth(&mut self, env: &mut TypeEnv) -> Result<Id> {
+        let live = check_patterns(self, env)?;
+        if self.branches.is_empty() {
+            return Err(anyhow::anyhow!("case must have at least one branch"));
+        }
+        // the join of the branch types: `'fizz'` and `(to_string x)` is `str`,
+        // `:ok` and `:err` is `(| :ok :err)`
+        let mut ret_ty = None;
+        for ((pattern, body), live) in self.branches.iter_mut().zip(live) {
+            let ty = env.scoped(|env| body.synth(env))?;
+            if !live {
+                continue;
+            }
+            ret_ty = Some(match ret_ty {
+                Some(acc) => join(env, acc, ty).map_err(|e| branch_error(pattern, e))?,
+                None => ty,
+            });
+        }
+        let ret_ty = ret_ty.ok_or(anyhow::anyhow!("case has no reachable branch"))?;
+        bound_literals(env, ret_ty)
+    }
+
+    fn check(&mut self, env: &mut TypeEnv, expected: Id) -> Result<Id> {
+        let live = check_patterns(self, env)?;
+        for ((pattern, body), live) in self.branches.iter_mut().zip(live) {
+            env.scoped(|env| {
+                if live {
+                    body.check(env, expected)
+                } else {
+                    body.synth(env)
                 }
-                let body_ty = body.type_check(env)?;
-                Ok(body_ty)
             })
-            .collect::<Result<Vec<_>>>()?;
-        if body_tys.iter().collect::<HashSet<_>>().len() != 1 {
-            return Err(anyhow::anyhow!(
-                "case branches must have same type: [{}]",
-                body_tys
-                    .iter()
-                    .map(|id| env.type_name(*id).map(|t| t.to_string()))
-                    .collect::<Result<Vec<_>>>()?
-                    .join(", ")
-            ));
+            .map_err(|e| branch_error(pattern, e))?;
+        }
+        Ok(expected)
+    }
+}
+
+impl TypeCheck for Import {
+    /// binds exported names and type names of a loaded module as `module.name`, and the imported ones unqualified
+    fn synth(&mut self, env: &mut TypeEnv) -> Result<Id> {
+        let interface = env.module_interface(&self.path)?.clone();
+        for (name, ty) in &interface.exports {
+            env.set_variable(&format!("{}.{}", interface.name, name), *ty);
+        }
+        for (name, ty) in &interface.types {
+            env.import_type(&format!("{}.{}", interface.name, name), *ty);
+        }
+        let names = match &self.names {
+            ImportNames::Qualified => vec![],
+            ImportNames::Only(names) => names.clone(),
+            ImportNames::All => interface
+                .exports
+                .keys()
+                .chain(interface.types.keys())
+                .cloned()
+                .collect(),
+        };
+        for name in names {
+            let ty = interface.exports.get(&name);
+            let type_name = interface.types.get(&name);
+            if ty.is_none() && type_name.is_none() {
+                return Err(anyhow::anyhow!(
+                    "{} is not exported by module {}",
+                    name,
+                    interface.name
+                ));
+            }
+            if let Some(ty) = ty {
+                env.set_variable(&name, *ty);
+            }
+            if let Some(ty) = type_name {
+                env.import_type(&name, *ty);
+            }
+        }
+        env.new_type_str("atom")
+    }
+}
+
+impl TypeCheck for Module {
+    /// the body is checked in the current scope so that its bindings can be exported
+    fn synth(&mut self, env: &mut TypeEnv) -> Result<Id> {
+        for expr in &mut self.body {
+            expr.synth(env)?;
+        }
+        for name in self.exports() {
+            env.get_variable(&name).map_err(|_| {
+                anyhow::anyhow!("exported {} is not defined in {}", name, self.name)
+            })?;
+        }
+        env.new_type_str("atom")
+    }
+}
+
+/// records the hole `expr` expected to be of type `expected`
+fn hole(expr: &Expr, env: &mut TypeEnv, expected: Id) -> Result<Id> {
+    let scope = env.visible_variables();
+    env.holes.push(Hole {
+        name: expr.to_string(),
+        expected,
+        scope,
+    });
+    Ok(expected)
+}
+
+/// describes the expected type of `hole` and the variables in its scope which fit the type
+fn describe_hole(env: &mut TypeEnv, hole: &Hole) -> Result<String> {
+    let expected = prune(&mut env.alloc, hole.expected);
+    let expected = type_eval(env, expected)?;
+    let mut report = format!("hole {} : {}", hole.name, env.type_name(expected)?);
+    // anything fits a hole of an unknown type
+    if matches!(env.alloc.get(expected)?, Type::Variable { .. }) {
+        return Ok(report);
+    }
+    let mut fits = vec![];
+    for (name, ty) in &hole.scope {
+        // `prelude.id` is omitted if imported as `id`
+        let imported = name
+            .split_once('.')
+            .is_some_and(|(_, unqualified)| hole.scope.get(unqualified) == Some(ty));
+        if imported {
+            continue;
         }
-        let ret_ty = body_tys[0];
-        Ok(ret_ty)
+        // tried on a copy as unification instantiates type variables
+        let mut trial = env.clone();
+        let ty = fresh(&mut trial, *ty);
+        if unify(&mut trial, ty, expected).is_ok() {
+            fits.push(name.as_str());
+        }
+    }
+    if !fits.is_empty() {
+        report.push_str(&format!(", fits: {}", fits.join(" ")));
     }
+    Ok(report)
 }
 
 impl TypeCheck for Expr {
-    fn type_check(&self, env: &mut TypeEnv) -> Result<Id> {
-        let _span = tracing::debug_span!("", "{}", self).entered();
+    fn synth(&mut self, env: &mut TypeEnv) -> Result<Id> {
+        let _span = tracing::debug_span!("synth", "{}", self).entered();
         let res = match self {
-            Expr::Literal(value) => value.type_check(env),
-            Expr::Variable(name) => env.get_variable(name),
-            Expr::Let(lt) => lt.type_check(env),
-            Expr::FnApp(app) => app.type_check(env),
-            Expr::FnDef(fn_def) => fn_def.type_check(env),
-            Expr::TypeDef(type_def) => type_def.type_check(env),
-            Expr::Case(case) => case.type_check(env),
-            Expr::Include(_) => Ok(env.new_type_str("str")?),
+            Expr::Literal(value) => value.synth(env),
+            Expr::Variable(name) => {
+                let id = env.get_variable(name)?;
+                Ok(fresh(env, id))
+            }
+            Expr::Let(lt) => lt.synth(env),
+            Expr::LetIn(let_in) => let_in.synth(env),
+            Expr::Do(exprs) => type_do(exprs, env, None),
+            Expr::FnApp(app) => app.synth(env),
+            Expr::FnDef(fn_def) => fn_def.synth(env),
+            Expr::TypeDef(type_def) => type_def.synth(env),
+            Expr::Case(case) => case.synth(env),
+            Expr::Module(module) => module.synth(env),
+            Expr::Export(_) => env.new_type_str("atom"),
+            Expr::Import(import) => import.synth(env),
+            // the type is decided by the enclosing expressions, like arguments of `FnApp`
+            Expr::Hole(_) => {
+                let id = new_variable(env);
+                hole(self, env, id)
+            }
+            Expr::Blame(blame) => {
+                blame.expr.synth(env)?;
+                env.new_type(&blame.typ)
+            }
+            Expr::The(the) => {
+                let ty = env.new_declared_type(&the.typ)?;
+                let ty = type_eval(env, ty)?;
+                let ty = fresh(env, ty);
+                the.expr.check(env, ty)
+            }
+            // any value can be cast. the result is `:invalid-cast` if it is not of the type
+            Expr::Cast(cast) => {
+                cast.expr.synth(env)?;
+                cast.module = env.module().map(str::to_string);
+                // the interpreter checks the value against the type outside of the module
+                cast.typ = env.resolve_names(&cast.typ)?;
+                let id = env.new_type(&union(vec![
+                    cast.typ.clone(),
+                    Sexp::String(format!(":{}", Cast::INVALID)),
+                ]))?;
+                let id = type_eval(env, id)?;
+                Ok(fresh(env, id))
+            }
+        }?;
+        log::debug!(":{} #{}", env.type_name(res)?, res);
+        Ok(res)
+    }
+
+    fn check(&mut self, env: &mut TypeEnv, expected: Id) -> Result<Id> {
+        let _span = tracing::debug_span!("check", "{}", self).entered();
+        let res = match self {
+            Expr::Literal(value) => value.check(env, expected),
+            Expr::FnDef(fn_def) => fn_def.check(env, expected),
+            Expr::Case(case) => case.check(env, expected),
+            Expr::LetIn(let_in) => let_in.check(env, expected),
+            Expr::Do(exprs) => type_do(exprs, env, Some(expected)),
+            Expr::Hole(_) => hole(self, env, expected),
+            _ => subsume_dyn(self, env, expected),
         }?;
         log::debug!(":{} #{}", env.type_name(res)?, res);
         Ok(res)
@@ -190,49 +904,573 @@ impl TypeCheck for Expr {
 }
 
 impl TypeCheck for Program {
-    fn type_check(&self, env: &mut TypeEnv) -> Result<Id> {
+    fn synth(&mut self, env: &mut TypeEnv) -> Result<Id> {
         let id = *self
             .0
-            .iter()
-            .map(|expr| expr.type_check(env))
+            .iter_mut()
+            .map(|expr| expr.synth(env))
             .collect::<Result<Vec<_>>>()?
             .last()
-            .unwrap();
+            .ok_or(anyhow::anyhow!("empty program"))?;
+        // programs with holes can't be evaluated. all of them are reported at once
+        let holes = std::mem::take(&mut env.holes);
+        if !holes.is_empty() {
+            let reports = holes
+                .iter()
+                .map(|hole| describe_hole(env, hole))
+                .collect::<Result<Vec<_>>>()?;
+            return Err(anyhow::anyhow!("{}", reports.join("\n")));
+        }
         Ok(id)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{tests::setup, type_check::TypeCheck};
+    use crate::{
+        tests::setup,
+        type_check::{TypeCheck, MAX_LITERAL_UNION},
+        type_env::{ModuleInterface, TypeEnv},
+    };
     use anyhow::Result;
-    use ast::into_ast::into_ast;
+    use ast::{ast::Program, into_ast::into_ast};
+    use std::collections::BTreeMap;
     use symbolic_expressions::parser::parse_str;
 
     #[test]
     fn r#let() -> Result<()> {
         setup();
-        for (expected, error) in [
-            ("(let x : (record (a : int)) (record (a : 3)))", None),
+        for expr in [
+            "(let x : (record (a : int)) (record (a : 3)))",
+            "(let x : (record (a : any) (b : bool)) (record (a : 1) (b : true)))",
+        ] {
+            into_ast(&parse_str(expr)?)?.synth(&mut Default::default())?;
+        }
+        should_fail_synth(
+            &mut Default::default(),
+            "(let x : (record (a : bool) (b : int)) (record (b : 1) (a : 2)))",
+            "2 is not subtype of bool",
+        )
+    }
+
+    #[test]
+    fn let_polymorphic() -> Result<()> {
+        let mut env = TypeEnv::default();
+        let add = env.new_type_str("((int int) -> int)")?;
+        env.set_variable("+", add);
+        should_fail_synth(
+            &mut env,
+            "(let f : ((a) -> a) (fn x 1))",
+            "1 is not subtype of a",
+        )?;
+        // type variables of annotations are not instantiated by the definitions
+        should_synth(&mut env, "(let g (fn (x : a) (+ x 1)))", "((int) -> int)")?;
+        should_synth(&mut env, "(let id : ((a) -> a) (fn x x))", "((a) -> a)")?;
+        should_synth(&mut env, "(id 'str')", "'str'")?;
+        should_synth(&mut env, "(id 1)", "1")
+    }
+
+    #[test]
+    fn getter() -> Result<()> {
+        setup();
+        let mut env = TypeEnv::default();
+        let getter = env.new_type_str("((r (k <: (keyof r))) -> ([] r k))")?;
+        env.set_variable("[]", getter);
+        for expr in [
+            "(type t : (record (x : int) (y : bool)))",
+            "(let a : t (record (x : 1) (y : true)))",
+            "(let x : int ([] a :x))",
+        ] {
+            into_ast(&parse_str(expr)?)?.synth(&mut env)?;
+        }
+        should_fail_synth(
+            &mut env,
+            "([] a :z)",
+            ":z is not subtype of (keyof (record (x : int) (y : bool)))",
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn newtype() -> Result<()> {
+        setup();
+        let mut env = TypeEnv::default();
+        for expr in [
+            "(newtype user-id : int)",
+            "(newtype order-id : int)",
+            "(let u : user-id (wrap-user-id 1))",
+            "(let n : int (unwrap-user-id u))",
+        ] {
+            into_ast(&parse_str(expr)?)?.synth(&mut env)?;
+        }
+        for (expr, error) in [
+            ("(let o : order-id u)", "user-id is not subtype of order-id"),
+            ("(let i : int u)", "user-id is not subtype of int"),
+            ("(let v : user-id 1)", "1 is not subtype of user-id"),
+        ] {
+            should_fail_synth(&mut env, expr, error)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn opaque() -> Result<()> {
+        setup();
+        let mut env = TypeEnv::default();
+        env.set_module(Some("counter.sexp".to_string()));
+        for expr in [
+            "(opaque counter : int)",
+            "(let zero : counter 0)",
+            "(let n : int zero)",
+        ] {
+            into_ast(&parse_str(expr)?)?.synth(&mut env)?;
+        }
+        env.register_module(
+            "counter.sexp",
+            ModuleInterface {
+                name: "counter".to_string(),
+                exports: BTreeMap::new(),
+                types: env.module_types("counter.sexp"),
+            },
+        );
+        // type names are visible in the defining module and its importers only
+        env.set_module(Some("main.sexp".to_string()));
+        should_fail_synth(
+            &mut env,
+            "(let z : counter zero)",
+            "counter.counter is not subtype of counter",
+        )?;
+        for expr in [
+            "(import counter.sexp *)",
+            "(let z : counter zero)",
+            "(let q : counter.counter zero)",
+        ] {
+            into_ast(&parse_str(expr)?)?.synth(&mut env)?;
+        }
+        for (expr, error) in [
             (
-                "(let x : (record (a : any) (b : bool)) (record (a : 1) (b : true)))",
-                None,
+                "(let one : counter 1)",
+                "1 is not subtype of counter.counter",
             ),
             (
-                "(let x : (record (a : bool) (b : int)) (record (b : 1) (a : 2)))",
-                Some(
-                    "(record (a : 2) (b : 1)) is not subtype of (record (a : bool) (b : int))"
-                        .to_string(),
-                ),
+                "(let m : int zero)",
+                "counter.counter is not subtype of int",
             ),
         ] {
-            let ast = into_ast(&parse_str(expected)?)?;
-            assert_eq!(
-                ast.type_check(&mut Default::default())
-                    .err()
-                    .map(|e| e.to_string()),
-                error
-            );
+            should_fail_synth(&mut env, expr, error)?;
+        }
+        // aliases in other modules don't shadow them
+        env.set_module(Some("other.sexp".to_string()));
+        into_ast(&parse_str("(type counter : str)")?)?.synth(&mut env)?;
+        into_ast(&parse_str("(let s : counter 'a')")?)?.synth(&mut env)?;
+        env.set_module(Some("counter.sexp".to_string()));
+        into_ast(&parse_str("(let n : counter zero)")?)?.synth(&mut env)?;
+        Ok(())
+    }
+
+    fn should_synth(env: &mut TypeEnv, expr: &str, type_expr: &str) -> Result<()> {
+        setup();
+        let expected = parse_str(type_expr)?;
+        let ty = into_ast(&parse_str(expr)?)?.synth(env)?;
+        assert_eq!(expected, env.type_name(ty)?);
+        Ok(())
+    }
+
+    fn should_fail_synth(env: &mut TypeEnv, expr: &str, error: &str) -> Result<()> {
+        setup();
+        let err = into_ast(&parse_str(expr)?)?
+            .synth(env)
+            .err()
+            .map(|e| e.to_string());
+        assert_eq!(err, Some(error.to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn synth_literal() -> Result<()> {
+        let mut env = TypeEnv::default();
+        should_synth(&mut env, "true", "true")?;
+        should_synth(&mut env, "1", "1")?;
+        should_synth(&mut env, "-3", "-3")?;
+        should_synth(&mut env, "1.5", "1.5")?;
+        should_synth(&mut env, "0.00001", "0.00001")?;
+        should_synth(
+            &mut env,
+            "100000000000000000000.0",
+            "100000000000000000000.0",
+        )?;
+        should_synth(&mut env, "(let x : float 0.00001)", "float")?;
+        should_synth(&mut env, "(let x : float 100000000000000000000.0)", "float")?;
+        should_synth(&mut env, "(let x : float -0.00001)", "float")?;
+        should_synth(&mut env, "(let x : num 1.5)", "num")?;
+        should_synth(&mut env, "(record (a : 1))", "(record (a : 1))")?;
+        should_synth(&mut env, "(vec)", "(vec a)")?;
+        should_synth(&mut env, "(vec 1 2)", "(vec int)")?;
+        should_synth(&mut env, "(vec 1 'a' 2)", "(vec (| int str))")?;
+        should_synth(&mut env, "(vec 1 1.5)", "(vec (| int float))")?;
+        should_synth(&mut env, "(vec (vec 1) (vec))", "(vec (vec int))")?;
+        should_synth(
+            &mut env,
+            "(vec 'a' (vec 'b') 'c')",
+            "(vec (| str (vec str)))",
+        )?;
+        should_synth(&mut env, "(fn x (vec x 1))", "((int) -> (vec int))")?;
+        should_synth(&mut env, "'a'", "'a'")?;
+        should_synth(&mut env, "'int'", "'int'")?;
+        should_synth(&mut env, "\"'(x'\"", "\"'(x'\"")?;
+        should_synth(&mut env, "\"'a b'\"", "\"'a b'\"")?;
+        should_synth(&mut env, "'it's'", "'it\\'s'")?;
+        should_synth(&mut env, "(let s : (| 'a' 'b') 'a')", "(| 'a' 'b')")?;
+        should_synth(&mut env, "(case (true => 'a') (false => 'b'))", "'a'")?;
+        should_synth(&mut env, "(dict ('a' : 1) ('b' : 2))", "(dict str int)")?;
+        should_synth(&mut env, "(dict (:a : true))", "(dict atom bool)")?;
+        should_synth(&mut env, "(dict)", "(dict a b)")?;
+        for (expr, error) in [
+            ("(dict (1 : 1))", "int is not subtype of (| atom str)"),
+            ("(dict ('a' : 1) (:b : 2))", ":b is not subtype of str"),
+            (
+                "(let d : (dict int int) (dict))",
+                "int is not subtype of (| atom str)",
+            ),
+        ] {
+            should_fail_synth(&mut env, expr, error)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn synth_fn() -> Result<()> {
+        let mut env = TypeEnv::default();
+        should_synth(&mut env, "(fn (x : int) 1)", "((int) -> 1)")?;
+        should_synth(&mut env, "(fn x y x)", "((a b) -> a))")
+    }
+
+    #[test]
+    fn synth_app() -> Result<()> {
+        let mut env = TypeEnv::default();
+        let ty = env.new_type_str("((bool) -> bool)")?;
+        env.set_variable("not", ty);
+        should_synth(&mut env, "(not true)", "bool")
+    }
+
+    #[test]
+    fn let_widen() -> Result<()> {
+        let mut env = TypeEnv::default();
+        should_synth(&mut env, "(let x 1)", "int")?;
+        should_synth(&mut env, "(let x :a)", "atom")?;
+        should_synth(&mut env, "(let x : const 1)", "1")?;
+        should_synth(&mut env, "(let x : (| 1 2) 1)", "(| 1 2)")?;
+        should_synth(
+            &mut env,
+            "(let x (record (a : 1) (b : 'b')))",
+            "(record (a : int) (b : str))",
+        )?;
+        should_synth(
+            &mut env,
+            "(let x : const (record (a : 1)))",
+            "(record (a : 1))",
+        )?;
+        should_synth(
+            &mut env,
+            "(let x (vec (record (a : true))))",
+            "(vec (record (a : bool)))",
+        )?;
+        should_synth(&mut env, "(do (let x : const 1) (let y : 1 x))", "1")?;
+        // unions of literals, like the tags returned by `case`, are kept
+        let ty = env.new_type_str("bool")?;
+        env.set_variable("b", ty);
+        should_synth(
+            &mut env,
+            "(do (let r (case (b => :ok) (true => :err))) (let s : (| :ok :err) r))",
+            "(| :ok :err)",
+        )?;
+        should_fail_synth(
+            &mut env,
+            "(do (let x 1) (let y : 1 x))",
+            "int is not subtype of 1",
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn case_join() -> Result<()> {
+        let mut env = TypeEnv::default();
+        let ty = env.new_type_str("((int) -> str)")?;
+        env.set_variable("to_string", ty);
+        let ty = env.new_type_str("bool")?;
+        env.set_variable("b", ty);
+        should_synth(
+            &mut env,
+            "(case (b => 'fizz') (true => (to_string 1)))",
+            "str",
+        )?;
+        should_synth(&mut env, "(case (b => 1) (true => :none))", "(| 1 :none)")?;
+        should_synth(&mut env, "(case (b => :ok) (true => :err))", "(| :ok :err)")?;
+        should_synth(
+            &mut env,
+            "(case (b => 1) (b => 1.5) (true => 2))",
+            "(| 1 1.5 2)",
+        )?;
+        should_synth(
+            &mut env,
+            "(case (b => (record (a : 1))) (true => (record (a : 'a'))))",
+            "(record (a : (| 1 'a')))",
+        )?;
+        should_synth(
+            &mut env,
+            "(let x : (| 1 :none) (case (b => 1) (true => :none)))",
+            "(| 1 :none)",
+        )?;
+        should_fail_synth(
+            &mut env,
+            "(let x : str (case (b => 'a') (true => 1)))",
+            "case branch true: 1 is not subtype of str",
+        )?;
+        // literals of a base type are widened when there are too many of them
+        let branches = (0..=MAX_LITERAL_UNION)
+            .map(|i| format!("(b => {})", i))
+            .collect::<String>();
+        should_synth(
+            &mut env,
+            &format!("(case {} (true => :none))", branches),
+            "(| int :none)",
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn case_guard() -> Result<()> {
+        let mut env = TypeEnv::default();
+        let ty = env.new_type_str("((int int) -> bool)")?;
+        env.set_variable("==", ty);
+        should_synth(
+            &mut env,
+            "(case ((== 1 2) => 1) (true => 'a'))",
+            "(| 1 'a')",
+        )?;
+        // branches after `true` and with `false` are unreachable
+        should_synth(&mut env, "(case (true => 1) (false => 'a'))", "1")?;
+        should_synth(&mut env, "(case (false => 'a') ((== 1 2) => 1))", "1")?;
+        should_synth(
+            &mut env,
+            "(let x : int (case (true => 1) ((== 1 2) => 'a')))",
+            "int",
+        )?;
+        should_synth(
+            &mut env,
+            "(do (type t : (| true)) (let b : t true) (case (b => 1) (true => 'a')))",
+            "1",
+        )?;
+        for (expr, error) in [
+            ("(case (1 => 1))", "pattern 1 must be bool but 1"),
+            ("(case (false => 1))", "case has no reachable branch"),
+            (
+                "(case (true => 1) (false => (== 1 'a')))",
+                "'a' is not subtype of int",
+            ),
+        ] {
+            should_fail_synth(&mut env, expr, error)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn hole() -> Result<()> {
+        setup();
+        for (program, report) in [
+            ("(let x : int _)", "hole _ : int"),
+            (
+                "(let ((n 1) (s 'a')) (+ n ?rhs))",
+                "hole ?rhs : int, fits: n",
+            ),
+            ("(map ?f (vec 1))", "hole ?f : ((int) -> a)"),
+            (
+                "(let ((b true)) (let x : str (case (b => 'a') (true => _))))",
+                "hole _ : str",
+            ),
+            (
+                "(let ((n 1)) (do _ (+ _ n)))",
+                "hole _ : a\nhole _ : int, fits: n",
+            ),
+        ] {
+            let mut env = TypeEnv::default();
+            let ty = env.new_type_str("((int int) -> int)")?;
+            env.set_variable("+", ty);
+            let ty = env.new_type_str("((((a) -> b) (vec a)) -> (vec b))")?;
+            env.set_variable("map", ty);
+            let mut program = Program(vec![into_ast(&parse_str(program)?)?]);
+            let err = program.synth(&mut env).err().map(|e| e.to_string());
+            assert_eq!(err, Some(report.to_string()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn dyn_cast() -> Result<()> {
+        setup();
+        let mut env = TypeEnv::default();
+        let ty = env.new_type_str("((int int) -> int)")?;
+        env.set_variable("+", ty);
+        let ty = env.new_type_str("((((a) -> b) (vec a)) -> (vec b))")?;
+        env.set_variable("map", ty);
+        let ty = env.new_type_str("((r (k <: (keyof r))) -> ([] r k))")?;
+        env.set_variable("[]", ty);
+        should_synth(&mut env, "(let d : dyn 'a')", "dyn")?;
+        should_synth(&mut env, "(d 1 :a)", "dyn")?;
+        // type operators applied to `dyn`
+        should_synth(&mut env, "([] d :a)", "dyn")?;
+        for (expr, typ, elaborated) in [
+            ("(+ d 1)", "int", "(+ (the int d) 1)"),
+            // generic parameters are cast to the types resolved by the other arguments
+            (
+                "(map (fn x (+ x 1)) d)",
+                "(vec int)",
+                "(map (x) -> (+ x 1) (the (vec int) d))",
+            ),
+            ("(map (fn x x) d)", "(vec a)", "(map (x) -> x (the (vec dyn) d))"),
+            ("(let x : any d)", "any", "let x:any = d"),
+            ("(let x : dyn (+ 1 2))", "dyn", "let x:dyn = (+ 1 2)"),
+            (
+                "(let r : (record (a : int)) (record (a : d)))",
+                "(record (a : int))",
+                "let r:(record (a : int)) = (record (a : (the int d)))",
+            ),
+            // `dyn` nested in records and containers
+            (
+                "(do (let r : (record (a : dyn)) (record (a : 1))) (let s : (record (a : int)) r))",
+                "(record (a : int))",
+                "(do let r:(record (a : dyn)) = (record (a : 1)) let s:(record (a : int)) = (the (record (a : int)) r))",
+            ),
+            (
+                "(do (let v : (vec dyn) (vec 1)) (let w : (vec int) v))",
+                "(vec int)",
+                "(do let v:(vec dyn) = (vec 1) let w:(vec int) = (the (vec int) v))",
+            ),
+            // `dyn` guards are neither dead nor exhaustive
+            (
+                "(case (d => 1) (true => 2))",
+                "(| 1 2)",
+                "(case\n  ((the bool d) => 1)\n  (true => 2)\n)",
+            ),
+        ] {
+            let mut ast = into_ast(&parse_str(expr)?)?;
+            let ty = ast.synth(&mut env)?;
+            assert_eq!(env.type_name(ty)?, parse_str(typ)?);
+            assert_eq!(ast.to_string(), elaborated);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn the_cast() -> Result<()> {
+        setup();
+        let mut env = TypeEnv::default();
+        should_synth(&mut env, "(the int 1)", "int")?;
+        should_synth(&mut env, "(the (| 1 2) 1)", "(| 1 2)")?;
+        should_synth(
+            &mut env,
+            "(the (record (a : num)) (record (a : 1.5)))",
+            "(record (a : num))",
+        )?;
+        should_synth(&mut env, "(cast int 'a')", "(| int :invalid-cast)")?;
+        should_synth(
+            &mut env,
+            "(cast (| int str) :a)",
+            "(| int str :invalid-cast)",
+        )?;
+        should_fail_synth(&mut env, "(the int 'a')", "'a' is not subtype of int")?;
+        let mut ast = into_ast(&parse_str("(do (let d : dyn 1) (the int d))")?)?;
+        ast.synth(&mut env)?;
+        assert_eq!(ast.to_string(), "(do let d:dyn = 1 (the int (the int d)))");
+        Ok(())
+    }
+
+    #[test]
+    fn synth_generic_app() -> Result<()> {
+        let mut env = TypeEnv::default();
+        let ty = env.new_type_str("((a) -> a)")?;
+        env.set_variable("id", ty);
+        should_synth(&mut env, "id", "((a) -> a)")?;
+        should_synth(&mut env, "(id 1)", "1")?;
+        should_synth(&mut env, "(let a (id 1))", "int")?;
+        should_synth(&mut env, "(id :ok)", ":ok")?;
+        let ty = env.new_type_str("((int int) -> int)")?;
+        env.set_variable("+", ty);
+        should_synth(
+            &mut env,
+            "(fn x (fn y (+ x y)))",
+            "((int) -> ((int) -> int))",
+        )?;
+        should_synth(&mut env, "(fn f (f 1))", "((((1) -> a)) -> a)")?;
+        let ty = env.new_type_str("((((b a) -> b) b (vec a)) -> b)")?;
+        env.set_variable("fold", ty);
+        let ty = env.new_type_str("((int) -> (vec int))")?;
+        env.set_variable("range", ty);
+        should_synth(&mut env, "(fold (fn acc x (+ acc x)) 0 (range 3))", "int")?;
+        let ty = env.new_type_str("((a (vec a)) -> (vec a))")?;
+        env.set_variable("cons", ty);
+        should_synth(&mut env, "(cons 0 (range 3))", "(vec int)")
+    }
+
+    #[test]
+    fn check_propagates_annotation() -> Result<()> {
+        let mut env = TypeEnv::default();
+        let ty = env.new_type_str("((int int) -> int)")?;
+        env.set_variable("+", ty);
+        let ty = env.new_type_str("((((a) -> b) (vec a)) -> (vec b))")?;
+        env.set_variable("map", ty);
+        for expr in [
+            "(let xs : (vec bool) (vec true false))",
+            "(let f : ((int) -> int) (fn x (+ x 1)))",
+            "(let ys : (vec int) (map (fn x (+ x 1)) (vec 1)))",
+        ] {
+            into_ast(&parse_str(expr)?)?.synth(&mut env)?;
+        }
+        should_fail_synth(
+            &mut env,
+            "(let g : ((bool) -> int) (fn x (+ x 1)))",
+            "bool is not subtype of int",
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn scope() -> Result<()> {
+        setup();
+        let mut env = TypeEnv::default();
+        for expr in [
+            "(let x : bool true)",
+            "(let f (fn (x : int) x))",
+            "(let y : bool x)",
+            "(let g (fn (x : int) (fn (x : bool) x)))",
+        ] {
+            into_ast(&parse_str(expr)?)?.synth(&mut env)?;
+        }
+        should_synth(&mut env, "g", "((int) -> ((bool) -> bool))")?;
+        should_fail_synth(&mut env, "(fn (z : int) w)", "unbound variable w")?;
+        should_fail_synth(&mut env, "z", "unbound variable z")?;
+        Ok(())
+    }
+
+    #[test]
+    fn let_in() -> Result<()> {
+        setup();
+        let mut env = TypeEnv::default();
+        let ty = env.new_type_str("((int int) -> int)")?;
+        env.set_variable("+", ty);
+        should_synth(&mut env, "(let ((x 1) (y : int 2)) (+ x y))", "int")?;
+        should_synth(
+            &mut env,
+            "(fn (x : int) (let ((y (+ x 1))) (do (let z y) (+ y z))))",
+            "((int) -> int)",
+        )?;
+        should_synth(&mut env, "(let ((x true)) (let ((x 1)) x))", "int")?;
+        should_synth(&mut env, "(let ((x : const 1)) x)", "1")?;
+        for (expr, error) in [
+            ("(do (let ((x 1)) x) x)", "unbound variable x"),
+            ("(do (do (let y 1) y) y)", "unbound variable y"),
+        ] {
+            should_fail_synth(&mut env, expr, error)?;
         }
         Ok(())
     }
@@ -1,14 +1,16 @@
 use crate::{
     type_alloc::TypeAlloc,
     types::{
-        Id, Type, TypeExpr, FN_TYPE_KEYWORD, GETTER_TYPE_KEYWORD, LIST_TYPE_KEYWORD,
-        RECORD_TYPE_KEYWORD, SUBTYPE_KEYWORD, UNION_TYPE_KEYWORD,
+        Id, Type, TypeExpr, DICT_TYPE_KEYWORD, DYN_TYPE_KEYWORD, FN_TYPE_KEYWORD,
+        LIST_TYPE_KEYWORD, RECORD_TYPE_KEYWORD, SUBTYPE_KEYWORD, TYPE_OPERATORS,
+        UNION_TYPE_KEYWORD,
     },
 };
 use anyhow::Result;
 use std::{
-    collections::{BTreeMap, BTreeSet, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fmt::{Debug, Display},
+    path::Path,
 };
 use symbolic_expressions::{parser::parse_str, Sexp};
 
@@ -16,9 +18,52 @@ use symbolic_expressions::{parser::parse_str, Sexp};
 #[derive(Debug, Clone)]
 pub struct TypeEnv {
     pub alloc: TypeAlloc,
-    variables: HashMap<String, Id>,
+    /// lexical scopes of variables. the last frame is the innermost scope.
+    variables: Vec<HashMap<String, Id>>,
     /// key is stringified sexp
     id_map: HashMap<String, Id>,
+    /// the module (source file) being type checked
+    module: Option<String>,
+    /// types of parameters of enclosing functions, which must not be instantiated
+    pub(crate) non_generic: Vec<Id>,
+    /// type variables of declared polymorphic types, which must not be instantiated
+    pub(crate) rigid: HashSet<Id>,
+    /// remaining steps of the running [crate::type_eval::type_eval]
+    pub(crate) eval_fuel: Option<usize>,
+    /// interfaces of type checked modules. key is the resolved path
+    modules: HashMap<String, ModuleInterface>,
+    /// type names visible in each module, defined in or imported into it. key is the resolved path.
+    /// names defined outside of modules are in `id_map` and visible everywhere
+    type_names: HashMap<String, HashMap<String, Id>>,
+    /// type names defined by each module, exported to its importers
+    module_types: HashMap<String, BTreeMap<String, Id>>,
+    /// ids of primitive types looked up by [TypeEnv::primitive]
+    primitives: HashMap<&'static str, Id>,
+    /// results of [TypeEnv::is_subtype] for types without free variables
+    pub(crate) subtype_memo: HashMap<(Id, Id), bool>,
+    /// pairs being compared by [TypeEnv::is_subtype], assumed to be subtypes when met again
+    pub(crate) subtype_assumptions: HashSet<(Id, Id)>,
+    /// typed holes met by the type checker, reported after the program is checked
+    pub holes: Vec<Hole>,
+}
+
+/// a typed hole `_` or `?name` with the variables visible at it
+#[derive(Debug, Clone)]
+pub struct Hole {
+    pub name: String,
+    /// resolved after the enclosing expressions are checked
+    pub expected: Id,
+    pub scope: BTreeMap<String, Id>,
+}
+
+/// names and types exported by a type checked module
+#[derive(Debug, Clone)]
+pub struct ModuleInterface {
+    /// qualifier of the exported names: `name.x`
+    pub name: String,
+    pub exports: BTreeMap<String, Id>,
+    /// type names defined by the module
+    pub types: BTreeMap<String, Id>,
 }
 
 pub fn arrow(args: Vec<TypeExpr>, ret: TypeExpr) -> TypeExpr {
@@ -53,15 +98,73 @@ pub fn container(name: String, elements: Vec<TypeExpr>) -> TypeExpr {
     )
 }
 
+pub fn union(types: Vec<TypeExpr>) -> TypeExpr {
+    Sexp::List(
+        vec![Sexp::String(UNION_TYPE_KEYWORD.to_string())]
+            .into_iter()
+            .chain(types)
+            .collect(),
+    )
+}
+
+/// type operators evaluated by [crate::type_eval::type_eval]
+fn is_type_operator(head: &Sexp) -> bool {
+    head.is_string()
+        && head
+            .string()
+            .is_ok_and(|s| TYPE_OPERATORS.contains(&s.as_str()))
+}
+
+/// name of the literal type of the string `s`: `'s'`. quotes and backslashes in `s` are escaped.
+/// names with spaces or parentheses are printed double quoted by [Sexp]: `"'a b'"`
+pub fn str_literal(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// names of str literal types: `'a'`
+pub fn is_str_literal(name: &str) -> bool {
+    name.len() >= 2 && name.starts_with('\'') && name.ends_with('\'')
+}
+
+/// names of int literal types: `3`, `-3`. they may exceed the range of `i64`
+pub fn is_int_literal(name: &str) -> bool {
+    let digits = name.strip_prefix('-').unwrap_or(name);
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+/// names of float literal types: `1.5`, `-0.5`
+pub fn is_float_literal(name: &str) -> bool {
+    name.contains('.') && name.parse::<f64>().is_ok()
+}
+
+/// `(a <: b)`
+fn bounded_variable(sexp: &Sexp) -> Option<String> {
+    match sexp {
+        Sexp::List(list)
+            if list.len() == 3
+                && list[0].is_string()
+                && list[1].is_string()
+                && list[1].string().ok()? == SUBTYPE_KEYWORD =>
+        {
+            list[0].string().ok().cloned()
+        }
+        _ => None,
+    }
+}
+
 impl Default for TypeEnv {
     fn default() -> Self {
         let mut env = TypeEnv::new();
         env.new_type_str("any").unwrap();
+        env.new_type_str(DYN_TYPE_KEYWORD).unwrap();
         env.new_type_str("int").unwrap();
+        env.new_type_str("float").unwrap();
+        env.new_type_str("num").unwrap();
         env.new_type_str("bool").unwrap();
         env.new_type_str("atom").unwrap();
         env.new_type_str("str").unwrap();
         env.new_type_str("vec").unwrap();
+        env.new_type_str("dict").unwrap();
         env
     }
 }
@@ -70,9 +173,30 @@ impl TypeEnv {
     pub fn new() -> Self {
         Self {
             alloc: TypeAlloc::new(),
-            variables: HashMap::new(),
+            variables: vec![HashMap::new()],
             id_map: HashMap::new(),
+            module: None,
+            non_generic: vec![],
+            rigid: HashSet::new(),
+            eval_fuel: None,
+            modules: HashMap::new(),
+            type_names: HashMap::new(),
+            module_types: HashMap::new(),
+            primitives: HashMap::new(),
+            subtype_memo: HashMap::new(),
+            subtype_assumptions: HashSet::new(),
+            holes: vec![],
+        }
+    }
+
+    /// id of the primitive type `name` such as `int`, cached after the first lookup
+    pub fn primitive(&mut self, name: &'static str) -> Result<Id> {
+        if let Some(id) = self.primitives.get(name) {
+            return Ok(*id);
         }
+        let id = self.get(&Sexp::String(name.to_string()))?;
+        self.primitives.insert(name, id);
+        Ok(id)
     }
 
     pub fn get(&self, type_expr: &TypeExpr) -> Result<Id> {
@@ -94,8 +218,238 @@ impl TypeEnv {
         self.alloc.as_sexp(id)
     }
 
+    /// names the type `ty` in the current module. names defined outside of modules are visible everywhere
     pub fn new_alias(&mut self, name: &str, ty: Id) {
-        self.id_map.insert(name.to_string(), ty);
+        if let Some(module) = &self.module {
+            self.module_types
+                .entry(module.clone())
+                .or_default()
+                .insert(name.to_string(), ty);
+        }
+        self.import_type(name, ty);
+    }
+
+    /// makes the type `ty` of another module visible as `name` in the current module
+    pub fn import_type(&mut self, name: &str, ty: Id) {
+        match &self.module {
+            Some(module) => {
+                self.type_names
+                    .entry(module.clone())
+                    .or_default()
+                    .insert(name.to_string(), ty);
+            }
+            None => {
+                self.id_map.insert(name.to_string(), ty);
+            }
+        }
+    }
+
+    /// type names defined by the module in `path`
+    pub fn module_types(&self, path: &str) -> BTreeMap<String, Id> {
+        self.module_types.get(path).cloned().unwrap_or_default()
+    }
+
+    /// registers a nominal type `name` represented by `repr`
+    pub fn new_nominal(&mut self, name: &str, repr: Id) -> Id {
+        self.define_nominal(name, repr, None)
+    }
+
+    /// registers an opaque type `name` whose representation `repr` is visible in the current module only
+    pub fn new_opaque(&mut self, name: &str, repr: Id) -> Id {
+        let module = self.module.clone();
+        self.define_nominal(name, repr, module)
+    }
+
+    fn define_nominal(&mut self, name: &str, repr: Id, module: Option<String>) -> Id {
+        let id = self.alloc.issue_id();
+        let nominal_name = self.nominal_name(name);
+        self.alloc
+            .insert(Type::nominal(id, &nominal_name, repr, module));
+        self.id_map.insert(nominal_name, id);
+        self.new_alias(name, id);
+        id
+    }
+
+    /// the name of the nominal type `name` defined in the current module, qualified by the module: `counter.counter`.
+    /// types are printed by the name and looked up by it again, so it is unique among modules
+    fn nominal_name(&self, name: &str) -> String {
+        let Some(module) = &self.module else {
+            return name.to_string();
+        };
+        let stem = Path::new(module)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or(module.clone());
+        let qualified = format!("{}.{}", stem, name);
+        if self.id_map.contains_key(&qualified) {
+            format!("{}#{}", qualified, self.alloc.issue_id())
+        } else {
+            qualified
+        }
+    }
+
+    /// replaces the type names of the current module in the type expression `ty` with the types they name,
+    /// so that the result means the same in every module
+    pub fn resolve_names(&self, ty: &TypeExpr) -> Result<TypeExpr> {
+        match self
+            .module
+            .as_ref()
+            .and_then(|module| self.type_names.get(module))
+        {
+            Some(names) => self.resolve_names_rec(ty, names, &HashSet::new()),
+            None => Ok(ty.clone()),
+        }
+    }
+
+    fn resolve_names_rec(
+        &self,
+        ty: &TypeExpr,
+        names: &HashMap<String, Id>,
+        bound: &HashSet<String>,
+    ) -> Result<TypeExpr> {
+        let resolve_all = |types: &[Sexp], bound: &HashSet<String>| {
+            types
+                .iter()
+                .map(|t| self.resolve_names_rec(t, names, bound))
+                .collect::<Result<Vec<_>>>()
+        };
+        match ty {
+            Sexp::String(name) if !bound.contains(name) => match names.get(name) {
+                Some(id) => self.type_name(*id),
+                None => Ok(ty.clone()),
+            },
+            Sexp::List(list)
+                if list.len() == 3
+                    && list[1].is_string()
+                    && list[1].string()? == FN_TYPE_KEYWORD =>
+            {
+                // bounded type variables `(k <: t)` shadow type names until the end of the function type
+                let mut bound = bound.clone();
+                bound.extend(list[0].list()?.iter().filter_map(bounded_variable));
+                Ok(Sexp::List(vec![
+                    Sexp::List(resolve_all(list[0].list()?, &bound)?),
+                    list[1].clone(),
+                    self.resolve_names_rec(&list[2], names, &bound)?,
+                ]))
+            }
+            // `(label : t)` of records and `(k <: t)` of function types
+            Sexp::List(list)
+                if list.len() == 3
+                    && list[1].is_string()
+                    && [":", SUBTYPE_KEYWORD].contains(&list[1].string()?.as_str()) =>
+            {
+                Ok(Sexp::List(vec![
+                    list[0].clone(),
+                    list[1].clone(),
+                    self.resolve_names_rec(&list[2], names, bound)?,
+                ]))
+            }
+            // `(vec t)`, `(| t s)`, `(keyof t)`, ...
+            Sexp::List(list) if !list.is_empty() && list[0].is_string() => {
+                let mut resolved = vec![list[0].clone()];
+                resolved.extend(resolve_all(&list[1..], bound)?);
+                Ok(Sexp::List(resolved))
+            }
+            Sexp::List(list) => Ok(Sexp::List(resolve_all(list, bound)?)),
+            _ => Ok(ty.clone()),
+        }
+    }
+
+    /// the type written as `ty` in the current module
+    pub fn new_declared_type(&mut self, ty: &TypeExpr) -> Result<Id> {
+        let ty = self.resolve_names(ty)?;
+        self.new_type(&ty)
+    }
+
+    /// the module (source file) being type checked
+    pub fn module(&self) -> Option<&str> {
+        self.module.as_deref()
+    }
+
+    /// returns the previous module
+    pub fn set_module(&mut self, module: Option<String>) -> Option<String> {
+        // opaque types are related to their representation only inside their module
+        self.subtype_memo.clear();
+        std::mem::replace(&mut self.module, module)
+    }
+
+    /// the base type of a literal type: `1` -> `int`, `1.5` -> `float`, `true` -> `bool`, `:a` -> `atom`.
+    /// fields of records and elements of vecs and dicts are widened. other types, including recursive
+    /// ones, are returned as is.
+    pub fn widen(&mut self, id: Id) -> Result<Id> {
+        let id = crate::unify::prune(&mut self.alloc, id);
+        if self.alloc.is_recursive(id)? {
+            return Ok(id);
+        }
+        let name = match self.alloc.get(id)? {
+            Type::Primitive { name, .. } => name,
+            Type::Record { fields, .. } => {
+                let widened = fields
+                    .iter()
+                    .map(|(label, &field)| Ok((label.clone(), self.widen(field)?)))
+                    .collect::<Result<BTreeMap<_, _>>>()?;
+                if widened == fields {
+                    return Ok(id);
+                }
+                let id = self.alloc.issue_id();
+                self.alloc.insert(Type::record(id, widened));
+                return Ok(id);
+            }
+            // only vec and dict. arguments of type operators are not values
+            Type::Container {
+                id: constructor,
+                elements,
+            } if constructor == self.new_type_str(LIST_TYPE_KEYWORD)?
+                || constructor == self.new_type_str(DICT_TYPE_KEYWORD)? =>
+            {
+                let widened = elements
+                    .iter()
+                    .map(|&elem| self.widen(elem))
+                    .collect::<Result<Vec<_>>>()?;
+                if widened == elements {
+                    return Ok(id);
+                }
+                let id = self.alloc.issue_id();
+                self.alloc.insert(Type::container(constructor, widened));
+                return Ok(id);
+            }
+            // unions keep their literals: `(| 1 2)`
+            _ => return Ok(id),
+        };
+        let base = match name.as_str() {
+            "true" | "false" => "bool",
+            _ if name.starts_with(':') => "atom",
+            _ if is_str_literal(&name) => "str",
+            _ if is_int_literal(&name) => "int",
+            _ if is_float_literal(&name) => "float",
+            _ => return Ok(id),
+        };
+        self.new_type_str(base)
+    }
+
+    /// runs `f` as the module `module` with no variables in scope.
+    /// returns the variables bound at the top level of the module.
+    pub fn with_module<T>(
+        &mut self,
+        module: &str,
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<(T, HashMap<String, Id>)> {
+        let variables = std::mem::replace(&mut self.variables, vec![HashMap::new()]);
+        let prev = self.set_module(Some(module.to_string()));
+        let res = f(self);
+        self.set_module(prev);
+        let mut module_variables = std::mem::replace(&mut self.variables, variables);
+        Ok((res?, module_variables.swap_remove(0)))
+    }
+
+    pub fn register_module(&mut self, path: &str, interface: ModuleInterface) {
+        self.modules.insert(path.to_string(), interface);
+    }
+
+    pub fn module_interface(&self, path: &str) -> Result<&ModuleInterface> {
+        self.modules
+            .get(path)
+            .ok_or(anyhow::anyhow!("module {} is not loaded", path))
     }
 
     pub fn new_type(&mut self, ty: &TypeExpr) -> Result<Id> {
@@ -123,12 +477,29 @@ impl TypeEnv {
                     && list[1].string()? == FN_TYPE_KEYWORD =>
             {
                 anyhow::ensure!(list.len() == 3, "invalid function type {:?}", list);
+                // bounded type variables `(k <: t)` are visible by name until the end of the function type
+                let shadowed = list[0]
+                    .list()?
+                    .iter()
+                    .filter_map(bounded_variable)
+                    .map(|name| {
+                        let prev = self.id_map.get(&name).cloned();
+                        (name, prev)
+                    })
+                    .collect::<Vec<_>>();
                 let args = list[0]
                     .list()?
                     .iter()
                     .map(|s| self.new_type(s))
                     .collect::<Result<Vec<_>>>()?;
-                let ret = self.new_type(&list[2])?;
+                let ret = self.new_type(&list[2]);
+                for (name, prev) in shadowed {
+                    match prev {
+                        Some(id) => self.id_map.insert(name, id),
+                        None => self.id_map.remove(&name),
+                    };
+                }
+                let ret = ret?;
                 let id = self.alloc.issue_id();
                 log::debug!("new_type function: {} #{}", ty, id);
                 self.alloc.insert(Type::function(id, args, ret));
@@ -152,7 +523,11 @@ impl TypeEnv {
                 self.register_type_id(ty, id);
                 Ok(id)
             }
-            Sexp::List(list) if list[0].string()? == LIST_TYPE_KEYWORD => {
+            // (vec a), (dict k v)
+            Sexp::List(list)
+                if list[0].string()? == LIST_TYPE_KEYWORD
+                    || list[0].string()? == DICT_TYPE_KEYWORD =>
+            {
                 let elements = list[1..]
                     .iter()
                     .map(|s| self.new_type(s))
@@ -163,13 +538,15 @@ impl TypeEnv {
                 self.register_type_id(ty, id);
                 Ok(id)
             }
-            // ([] a b)
-            Sexp::List(list) if list[0].string()? == GETTER_TYPE_KEYWORD => {
+            // ([] a b), (keyof a), (if (<: a b) c d), ...
+            Sexp::List(list) if is_type_operator(&list[0]) => {
                 let con = self.new_type(&list[0])?;
-                let a = self.new_type(&list[1])?;
-                let b = self.new_type(&list[2])?;
+                let operands = list[1..]
+                    .iter()
+                    .map(|s| self.new_type(s))
+                    .collect::<Result<Vec<_>>>()?;
                 let id = self.alloc.issue_id();
-                self.alloc.insert(Type::container(con, vec![a, b]));
+                self.alloc.insert(Type::container(con, operands));
                 self.register_type_id(ty, id);
                 Ok(id)
             }
@@ -193,9 +570,10 @@ impl TypeEnv {
                 if !is_type_var {
                     return Err(anyhow::anyhow!("must be type variable: {:?}", list[0]));
                 }
+                let upper_bound = self.new_type(&list[2])?;
                 let id = self.alloc.issue_id();
                 self.register_type_id(ty, id);
-                let upper_bound = self.get(&list[2])?;
+                self.register_type_id(&list[0], id);
                 log::debug!("new_type variable: {} <: {} #{}", ty, &list[2], id);
                 self.alloc.insert(Type::variable(id, Some(upper_bound)));
                 Ok(id)
@@ -211,15 +589,52 @@ impl TypeEnv {
         self.new_type(&parse_str(ty)?)
     }
 
+    /// binds a variable in the innermost scope
     pub fn set_variable(&mut self, name: &str, ty: Id) {
-        self.variables.insert(name.to_string(), ty);
+        self.variables
+            .last_mut()
+            .expect("global scope")
+            .insert(name.to_string(), ty);
     }
 
     pub fn get_variable(&self, name: &str) -> Result<Id> {
         self.variables
-            .get(name)
+            .iter()
+            .rev()
+            .find_map(|frame| frame.get(name))
             .cloned()
-            .ok_or(anyhow::anyhow!("{} not found", name))
+            .ok_or(anyhow::anyhow!("unbound variable {}", name))
+    }
+
+    /// `dyn` itself, not a type consistent with it
+    pub fn is_dyn(&mut self, id: Id) -> Result<bool> {
+        let id = crate::unify::prune(&mut self.alloc, id);
+        Ok(id == self.primitive(DYN_TYPE_KEYWORD)?)
+    }
+
+    /// variables in scope. inner bindings shadow outer ones
+    pub fn visible_variables(&self) -> BTreeMap<String, Id> {
+        self.variables
+            .iter()
+            .flat_map(|frame| frame.iter().map(|(name, ty)| (name.clone(), *ty)))
+            .collect()
+    }
+
+    pub fn push_scope(&mut self) {
+        self.variables.push(HashMap::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        assert!(self.variables.len() > 1, "global scope can't be popped");
+        self.variables.pop();
+    }
+
+    /// runs `f` in a new scope, which is popped even if `f` fails
+    pub fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.push_scope();
+        let res = f(self);
+        self.pop_scope();
+        res
     }
 
     fn register_type_id(&mut self, expr: &TypeExpr, type_id: Id) {
@@ -1,12 +1,18 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
 use crate::{
-    type_env::TypeEnv,
-    types::{Id, Type, GETTER_TYPE_KEYWORD, UNION_TYPE_KEYWORD},
+    type_env::{record as record_type, union, TypeEnv},
+    types::{
+        Id, Type, DYN_TYPE_KEYWORD, GETTER_TYPE_KEYWORD, IF_TYPE_KEYWORD, KEYOF_TYPE_KEYWORD,
+        MAP_FIELDS_TYPE_KEYWORD, SUBTYPE_KEYWORD, TYPE_OPERATORS, UNION_TYPE_KEYWORD,
+    },
 };
-use anyhow::Result;
+use anyhow::{Context, Result};
 use symbolic_expressions::Sexp;
 
+/// maximum number of steps of a single [type_eval]
+pub const TYPE_EVAL_FUEL: usize = 1000;
+
 pub fn ensure_subtype(env: &mut TypeEnv, a: Id, b: Id) -> Result<()> {
     if !env.is_subtype(a, b)? {
         return Err(anyhow::anyhow!(
@@ -18,12 +24,7 @@ pub fn ensure_subtype(env: &mut TypeEnv, a: Id, b: Id) -> Result<()> {
     Ok(())
 }
 
-fn eval_type_access(env: &mut TypeEnv, record: Id, key: Id) -> Result<Id> {
-    let record = type_eval(env, record)?;
-    let Type::Record { fields, .. } = env.alloc.get(record)? else {
-        return Err(anyhow::anyhow!("{} is not record type", record));
-    };
-    let key = type_eval(env, key)?;
+fn atom_name(env: &TypeEnv, key: Id) -> Result<String> {
     let Sexp::String(atom) = env.type_name(key)? else {
         return Err(anyhow::anyhow!(
             "{} #{} is not atom type",
@@ -31,30 +32,223 @@ fn eval_type_access(env: &mut TypeEnv, record: Id, key: Id) -> Result<Id> {
             key
         ));
     };
-    let key = atom.trim_start_matches(':');
-    fields.get(key).copied().ok_or_else(|| {
-        anyhow::anyhow!(
-            "key :{} not found in record {}",
-            key,
-            env.type_name(record).unwrap()
-        )
-    })
+    Ok(atom.trim_start_matches(':').to_string())
 }
 
-pub fn type_eval(env: &mut TypeEnv, id: Id) -> Result<Id> {
-    let t = env.type_name(id)?;
-    match t {
-        Sexp::List(list) if list[0].is_string() && list[0].string()? == GETTER_TYPE_KEYWORD => {
-            let (record, key) = (env.new_type(&list[1])?, env.new_type(&list[2])?);
-            eval_type_access(env, record, key)
+/// builds a union of `types`, or the type itself when there is exactly one
+fn union_of(env: &mut TypeEnv, types: Vec<Id>) -> Result<Id> {
+    if let [ty] = types.as_slice() {
+        return Ok(*ty);
+    }
+    let types = types
+        .into_iter()
+        .map(|t| env.type_name(t))
+        .collect::<Result<Vec<_>>>()?;
+    let id = env.new_type(&union(types))?;
+    type_eval(env, id)
+}
+
+fn is_unresolved(env: &TypeEnv, id: Id) -> Result<bool> {
+    Ok(matches!(
+        env.alloc.get(id)?,
+        Type::Variable { instance: None, .. }
+    ))
+}
+
+/// `([] T :key)` or `([] T (| :a :b))`
+fn eval_type_access(env: &mut TypeEnv, id: Id, record: Id, key: Id) -> Result<Id> {
+    let (record, key) = (type_eval(env, record)?, type_eval(env, key)?);
+    if is_unresolved(env, record)? || is_unresolved(env, key)? {
+        return Ok(id);
+    }
+    // type operators applied to `dyn` are `dyn`
+    if env.is_dyn(record)? || env.is_dyn(key)? {
+        return env.primitive(DYN_TYPE_KEYWORD);
+    }
+    let Type::Record { fields, .. } = env.alloc.get(record)? else {
+        return Err(anyhow::anyhow!("{} is not record type", record));
+    };
+    let keys = match env.alloc.get(key)? {
+        Type::Union { types, .. } => types.into_iter().collect::<Vec<_>>(),
+        _ => vec![key],
+    };
+    let field_tys = keys
+        .into_iter()
+        .map(|key| {
+            let key = atom_name(env, key)?;
+            fields.get(&key).copied().ok_or_else(|| {
+                anyhow::anyhow!(
+                    "key :{} not found in record {}",
+                    key,
+                    env.type_name(record).unwrap()
+                )
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    union_of(env, field_tys)
+}
+
+/// `(keyof T)` is the union of field atoms of record `T`
+fn eval_keyof(env: &mut TypeEnv, id: Id, record: Id) -> Result<Id> {
+    let record = type_eval(env, record)?;
+    if is_unresolved(env, record)? {
+        return Ok(id);
+    }
+    if env.is_dyn(record)? {
+        return env.primitive(DYN_TYPE_KEYWORD);
+    }
+    let Type::Record { fields, .. } = env.alloc.get(record)? else {
+        return Err(anyhow::anyhow!(
+            "{} is not record type",
+            env.type_name(record)?
+        ));
+    };
+    let keys = fields
+        .keys()
+        .map(|k| env.new_type(&Sexp::String(format!(":{}", k))))
+        .collect::<Result<Vec<_>>>()?;
+    union_of(env, keys)
+}
+
+/// `(map-fields T f)` applies the type constructor `f` to each field of record `T`.
+/// `f` is either a constructor name (`vec`) or a partial application (`(| :none)`).
+fn eval_map_fields(env: &mut TypeEnv, id: Id, record: Id, f: Id) -> Result<Id> {
+    let record = type_eval(env, record)?;
+    if is_unresolved(env, record)? {
+        return Ok(id);
+    }
+    if env.is_dyn(record)? {
+        return env.primitive(DYN_TYPE_KEYWORD);
+    }
+    let Type::Record { fields, .. } = env.alloc.get(record)? else {
+        return Err(anyhow::anyhow!(
+            "{} is not record type",
+            env.type_name(record)?
+        ));
+    };
+    let f = env.type_name(f)?;
+    let fields = fields
+        .into_iter()
+        .map(|(label, field)| {
+            let field = env.type_name(field)?;
+            let applied = match &f {
+                Sexp::String(_) => Sexp::List(vec![f.clone(), field]),
+                Sexp::List(partial) => Sexp::List(partial.iter().cloned().chain([field]).collect()),
+                Sexp::Empty => return Err(anyhow::anyhow!("empty type constructor")),
+            };
+            let applied = env.new_type(&applied)?;
+            let applied = type_eval(env, applied)?;
+            Ok((label, env.type_name(applied)?))
+        })
+        .collect::<Result<BTreeMap<_, _>>>()?;
+    env.new_type(&record_type(fields))
+}
+
+/// `(<: a b)` evaluates to literal type `true` or `false`
+fn eval_subtype(env: &mut TypeEnv, id: Id, a: Id, b: Id) -> Result<Id> {
+    let (a, b) = (type_eval(env, a)?, type_eval(env, b)?);
+    if is_unresolved(env, a)? || is_unresolved(env, b)? {
+        return Ok(id);
+    }
+    let res = env.is_subtype(a, b)?;
+    env.new_type(&Sexp::String(res.to_string()))
+}
+
+/// `(if (<: a b) then else)`
+fn eval_if(env: &mut TypeEnv, id: Id, cond: Id, then: Id, r#else: Id) -> Result<Id> {
+    let cond = type_eval(env, cond)?;
+    match env.type_name(cond)? {
+        Sexp::String(b) if b == "true" => type_eval(env, then),
+        Sexp::String(b) if b == "false" => type_eval(env, r#else),
+        _ if type_operator(env, cond)?.is_some() || is_unresolved(env, cond)? => Ok(id),
+        c => Err(anyhow::anyhow!(
+            "condition must evaluate to true or false but {}",
+            c
+        )),
+    }
+}
+
+/// returns operator name and operands if `id` is a type operator application
+fn type_operator(env: &TypeEnv, id: Id) -> Result<Option<(String, Vec<Id>)>> {
+    let Type::Container { id: con, elements } = env.alloc.get(id)? else {
+        return Ok(None);
+    };
+    match env.type_name(con)? {
+        Sexp::String(op) if TYPE_OPERATORS.contains(&op.as_str()) => Ok(Some((op, elements))),
+        _ => Ok(None),
+    }
+}
+
+fn eval_operator(env: &mut TypeEnv, id: Id, op: &str, operands: &[Id]) -> Result<Id> {
+    match (op, operands) {
+        (GETTER_TYPE_KEYWORD, [record, key]) => eval_type_access(env, id, *record, *key),
+        (KEYOF_TYPE_KEYWORD, [record]) => eval_keyof(env, id, *record),
+        (MAP_FIELDS_TYPE_KEYWORD, [record, f]) => eval_map_fields(env, id, *record, *f),
+        (SUBTYPE_KEYWORD, [a, b]) => eval_subtype(env, id, *a, *b),
+        (IF_TYPE_KEYWORD, [cond, then, r#else]) => eval_if(env, id, *cond, *then, *r#else),
+        _ => Err(anyhow::anyhow!(
+            "wrong number of operands for {}: {}",
+            op,
+            operands.len()
+        )),
+    }
+}
+
+fn consume_fuel(env: &mut TypeEnv) -> Result<()> {
+    match env.eval_fuel {
+        Some(0) => Err(anyhow::anyhow!(
+            "type evaluation exceeded {} steps",
+            TYPE_EVAL_FUEL
+        )),
+        Some(fuel) => {
+            env.eval_fuel = Some(fuel - 1);
+            Ok(())
         }
-        Sexp::List(list) if list[0].is_string() && list[0].string()? == UNION_TYPE_KEYWORD => {
+        None => Ok(()),
+    }
+}
+
+/// evaluates type operators in `id`.
+/// all nested evaluations share [TYPE_EVAL_FUEL] steps so that evaluation always terminates.
+pub fn type_eval(env: &mut TypeEnv, id: Id) -> Result<Id> {
+    let outermost = env.eval_fuel.is_none();
+    if outermost {
+        env.eval_fuel = Some(TYPE_EVAL_FUEL);
+    }
+    let res = type_eval_step(env, id);
+    if outermost {
+        env.eval_fuel = None;
+    }
+    res
+}
+
+fn type_eval_step(env: &mut TypeEnv, id: Id) -> Result<Id> {
+    consume_fuel(env)?;
+    if let Some((op, operands)) = type_operator(env, id)? {
+        let t = env.type_name(id)?;
+        return eval_operator(env, id, &op, &operands)
+            .with_context(|| format!("failed to evaluate {}", t));
+    }
+    match env.alloc.get(id)? {
+        Type::Variable {
+            instance: Some(instance),
+            ..
+        } => return type_eval_step(env, instance),
+        // keep identity of type variables and (possibly bounded) function signatures
+        Type::Variable { .. } | Type::Function { .. } => return Ok(id),
+        // type variables can't be restored from type names
+        _ if env.alloc.has_free_variables(id)? => return Ok(id),
+        // neither can recursive types, which have no finite name
+        _ if env.alloc.is_recursive(id)? => return Ok(id),
+        _ => {}
+    }
+    match env.alloc.get(id)? {
+        Type::Union { types, .. } => {
             // flatten union type
-            let mut types = list[1..]
-                .iter()
+            let mut types = types
+                .into_iter()
                 .map(|t| {
-                    let id = env.get(t)?;
-                    let t = type_eval(env, id)?;
+                    let t = type_eval(env, t)?;
                     let inner = if let Type::Union { types, .. } = env.alloc.get(t)? {
                         types
                     } else {
@@ -70,7 +264,10 @@ pub fn type_eval(env: &mut TypeEnv, id: Id) -> Result<Id> {
             types.insert(0, Sexp::String(UNION_TYPE_KEYWORD.to_string()));
             env.new_type(&Sexp::List(types))
         }
-        t => env.new_type(&t),
+        _ => {
+            let t = env.type_name(id)?;
+            env.new_type(&t)
+        }
     }
 }
 
@@ -99,4 +296,67 @@ mod tests {
         assert_type_eval("(| (| 1) 2)", "(| 1 2)")?;
         Ok(())
     }
+
+    #[test]
+    fn test_keyof() -> Result<()> {
+        assert_type_eval("(keyof (record (a : int) (b : bool)))", "(| :a :b)")?;
+        assert_type_eval("(keyof (record (a : int)))", ":a")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_type_access_union_key() -> Result<()> {
+        assert_type_eval(
+            "([] (record (a : int) (b : bool) (c : str)) (| :a :b))",
+            "(| int bool)",
+        )?;
+        assert_type_eval(
+            "([] (record (a : int) (b : bool)) (keyof (record (a : int) (b : bool))))",
+            "(| int bool)",
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_fields() -> Result<()> {
+        assert_type_eval(
+            "(map-fields (record (a : int) (b : bool)) vec)",
+            "(record (a : (vec int)) (b : (vec bool)))",
+        )?;
+        assert_type_eval(
+            "(map-fields (record (a : int)) (| :none))",
+            "(record (a : (| int :none)))",
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_conditional() -> Result<()> {
+        assert_type_eval("(if (<: 1 int) :yes :no)", ":yes")?;
+        assert_type_eval("(if (<: str int) :yes :no)", ":no")?;
+        assert_type_eval(
+            "(if (<: ([] (record (a : 1)) :a) int) (vec int) str)",
+            "(vec int)",
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_type_eval_error() -> Result<()> {
+        let mut env = TypeEnv::default();
+        let t = env.new_type_str("(if (<: ([] (record (a : int)) :b) int) :yes :no)")?;
+        let err = type_eval(&mut env, t).unwrap_err();
+        assert_eq!(
+            err.chain().map(|e| e.to_string()).collect::<Vec<_>>(),
+            vec![
+                "failed to evaluate (if (<: ([] (record (a : int)) :b) int) :yes :no)",
+                "failed to evaluate (<: ([] (record (a : int)) :b) int)",
+                "failed to evaluate ([] (record (a : int)) :b)",
+                "key :b not found in record (record (a : int))",
+            ]
+        );
+        let t = env.new_type_str("(if int :yes :no)")?;
+        assert!(type_eval(&mut env, t).is_err());
+        Ok(())
+    }
 }
@@ -6,10 +6,25 @@ pub type TypeExpr = Sexp;
 
 pub const RECORD_TYPE_KEYWORD: &str = "record";
 pub const LIST_TYPE_KEYWORD: &str = "vec";
+pub const DICT_TYPE_KEYWORD: &str = "dict";
 pub const GETTER_TYPE_KEYWORD: &str = "[]";
+pub const KEYOF_TYPE_KEYWORD: &str = "keyof";
+pub const MAP_FIELDS_TYPE_KEYWORD: &str = "map-fields";
+pub const IF_TYPE_KEYWORD: &str = "if";
 pub const FN_TYPE_KEYWORD: &str = "->";
 pub const UNION_TYPE_KEYWORD: &str = "|";
 pub const SUBTYPE_KEYWORD: &str = "<:";
+/// gradual type consistent with every type. uses as other types are checked at runtime
+pub const DYN_TYPE_KEYWORD: &str = "dyn";
+
+/// type-level operators, written in prefix form: `(op operand ...)`
+pub const TYPE_OPERATORS: [&str; 5] = [
+    GETTER_TYPE_KEYWORD,
+    KEYOF_TYPE_KEYWORD,
+    MAP_FIELDS_TYPE_KEYWORD,
+    SUBTYPE_KEYWORD,
+    IF_TYPE_KEYWORD,
+];
 
 #[derive(Debug, Clone, Hash, PartialEq)]
 pub enum Type {
@@ -39,6 +54,14 @@ pub enum Type {
         id: Id,
         types: BTreeSet<Id>,
     },
+    /// nominal type which is only subtype of itself.
+    /// if `module` is set, `repr` is visible inside the module (opaque type).
+    Nominal {
+        id: Id,
+        name: String,
+        repr: Id,
+        module: Option<String>,
+    },
 }
 
 impl Type {
@@ -50,6 +73,7 @@ impl Type {
             Type::Record { id, .. } => *id,
             Type::Container { id, .. } => *id,
             Type::Union { id, .. } => *id,
+            Type::Nominal { id, .. } => *id,
         }
     }
 
@@ -88,4 +112,13 @@ impl Type {
     pub fn container(id: Id, elements: Vec<Id>) -> Self {
         Type::Container { id, elements }
     }
+
+    pub fn nominal(id: Id, name: &str, repr: Id, module: Option<String>) -> Self {
+        Type::Nominal {
+            id,
+            name: name.to_string(),
+            repr,
+            module,
+        }
+    }
 }
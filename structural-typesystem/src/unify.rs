@@ -0,0 +1,221 @@
+use crate::{
+    type_alloc::TypeAlloc,
+    type_env::TypeEnv,
+    type_eval::ensure_subtype,
+    types::{Id, Type},
+};
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// returns an instance of t
+pub fn prune(alloc: &mut TypeAlloc, t: Id) -> Id {
+    match alloc.get(t) {
+        Ok(Type::Variable {
+            instance: Some(instance_id),
+            ..
+        }) => {
+            let pruned = prune(alloc, instance_id);
+            alloc.get_mut(t).unwrap().set_instance(pruned);
+            pruned
+        }
+        _ => t,
+    }
+}
+
+fn fresh_rec(env: &mut TypeEnv, tp: Id, mappings: &mut HashMap<Id, Id>) -> Id {
+    let p = prune(&mut env.alloc, tp);
+    if let Some(id) = mappings.get(&p) {
+        return *id;
+    }
+    if !env.alloc.has_free_variables(p).unwrap() {
+        return p;
+    }
+    let ty = match env.alloc.get(p).unwrap() {
+        Type::Variable { upper_bound, .. } => {
+            if !is_generic(&mut env.alloc, p, &env.non_generic.clone()) {
+                return p;
+            }
+            let id = env.alloc.issue_id();
+            env.alloc.insert(Type::variable(id, None));
+            mappings.insert(p, id);
+            if let Some(bound) = upper_bound {
+                let bound = fresh_rec(env, bound, mappings);
+                *env.alloc.get_mut(id).unwrap() = Type::variable(id, Some(bound));
+            }
+            return id;
+        }
+        Type::Primitive { .. } | Type::Nominal { .. } => return p,
+        Type::Function { args, ret, .. } => {
+            let args = args
+                .into_iter()
+                .map(|arg| fresh_rec(env, arg, mappings))
+                .collect();
+            let ret = fresh_rec(env, ret, mappings);
+            Type::function(env.alloc.issue_id(), args, ret)
+        }
+        Type::Record { fields, .. } => {
+            let fields = fields
+                .into_iter()
+                .map(|(label, id)| (label, fresh_rec(env, id, mappings)))
+                .collect();
+            Type::record(env.alloc.issue_id(), fields)
+        }
+        Type::Container { id, elements } => {
+            let elements = elements
+                .into_iter()
+                .map(|id| fresh_rec(env, id, mappings))
+                .collect();
+            Type::container(id, elements)
+        }
+        Type::Union { types, .. } => Type::Union {
+            types: types
+                .into_iter()
+                .map(|id| fresh_rec(env, id, mappings))
+                .collect(),
+            id: env.alloc.issue_id(),
+        },
+    };
+    let id = env.alloc.issue_id();
+    env.alloc.insert(ty);
+    id
+}
+
+/// instantiates generic type variables of `id` which are not bound by enclosing functions
+pub fn fresh(env: &mut TypeEnv, id: Id) -> Id {
+    let mut mappings: HashMap<Id, Id> = HashMap::new();
+    fresh_rec(env, id, &mut mappings)
+}
+
+/// instantiates type variables of `id` sharing `mappings`,
+/// so that annotations of one definition refer to the same variables
+pub fn fresh_with(env: &mut TypeEnv, id: Id, mappings: &mut HashMap<Id, Id>) -> Id {
+    fresh_rec(env, id, mappings)
+}
+
+/// instantiates type variables of a declared type as rigid variables, which [unify] never binds.
+/// values checked against the result must be polymorphic in them
+pub fn skolemize(env: &mut TypeEnv, id: Id) -> Id {
+    let mut mappings: HashMap<Id, Id> = HashMap::new();
+    let id = fresh_rec(env, id, &mut mappings);
+    env.rigid.extend(mappings.into_values());
+    id
+}
+
+fn bind(env: &mut TypeEnv, var: Id, ty: Id) -> Result<()> {
+    if occurs_in_type(&mut env.alloc, var, ty) {
+        return Err(anyhow::anyhow!(
+            "recursive unification: {} occurs in {}",
+            env.type_name(var)?,
+            env.type_name(ty)?
+        ));
+    }
+    // bounds referring to unresolved type variables are checked by the caller
+    if let Type::Variable {
+        upper_bound: Some(bound),
+        ..
+    } = env.alloc.get(var)?
+    {
+        if !env.alloc.has_free_variables(bound)? && !env.alloc.has_free_variables(ty)? {
+            ensure_subtype(env, ty, bound)?;
+        }
+    }
+    log::debug!("type variable #{} := #{}", var, ty);
+    env.alloc.get_mut(var)?.set_instance(ty);
+    Ok(())
+}
+
+/// unifies `sub` with `sup` so that `sub` is a subtype of `sup`.
+/// unresolved type variables on either side are instantiated.
+pub fn unify(env: &mut TypeEnv, sub: Id, sup: Id) -> Result<()> {
+    let (a, b) = (prune(&mut env.alloc, sub), prune(&mut env.alloc, sup));
+    if a == b {
+        return Ok(());
+    }
+    match (env.alloc.get(a)?, env.alloc.get(b)?) {
+        (_, Type::Variable { .. }) if !env.rigid.contains(&b) => bind(env, b, a),
+        (Type::Variable { .. }, _) if !env.rigid.contains(&a) => bind(env, a, b),
+        (
+            Type::Function {
+                args: a_args,
+                ret: a_ret,
+                ..
+            },
+            Type::Function {
+                args: b_args,
+                ret: b_ret,
+                ..
+            },
+        ) if a_args.len() == b_args.len() => {
+            // arguments are contravariant
+            for (a_arg, b_arg) in a_args.into_iter().zip(b_args) {
+                unify(env, b_arg, a_arg)?;
+            }
+            unify(env, a_ret, b_ret)
+        }
+        (
+            Type::Record {
+                fields: a_fields, ..
+            },
+            Type::Record {
+                fields: b_fields, ..
+            },
+        ) if a_fields.keys().eq(b_fields.keys()) => {
+            for (a_field, b_field) in a_fields.into_values().zip(b_fields.into_values()) {
+                unify(env, a_field, b_field)?;
+            }
+            Ok(())
+        }
+        (
+            Type::Container {
+                id: a_con,
+                elements: a_elements,
+            },
+            Type::Container {
+                id: b_con,
+                elements: b_elements,
+            },
+        ) if a_con == b_con && a_elements.len() == b_elements.len() => {
+            for (a_elem, b_elem) in a_elements.into_iter().zip(b_elements) {
+                unify(env, a_elem, b_elem)?;
+            }
+            Ok(())
+        }
+        _ => ensure_subtype(env, a, b),
+    }
+}
+
+fn is_generic(alloc: &mut TypeAlloc, id: Id, non_generic: &[Id]) -> bool {
+    !occurs_in(alloc, id, non_generic)
+}
+
+fn occurs_in(alloc: &mut TypeAlloc, id: Id, types: &[Id]) -> bool {
+    types.iter().any(|t| occurs_in_type(alloc, id, *t))
+}
+
+/// includes type variables in `t`
+fn occurs_in_type(alloc: &mut TypeAlloc, v: Id, t: Id) -> bool {
+    let prune_t = prune(alloc, t);
+    if prune_t == v {
+        return true;
+    }
+    match alloc.get(prune_t).unwrap() {
+        Type::Function { args, ret, .. } => {
+            let args_ret = args
+                .iter()
+                .cloned()
+                .chain(std::iter::once(ret))
+                .collect::<Vec<_>>();
+            occurs_in(alloc, v, &args_ret)
+        }
+        Type::Record { fields, .. } => occurs_in(
+            alloc,
+            v,
+            fields.values().cloned().collect::<Vec<_>>().as_slice(),
+        ),
+        Type::Container { elements, .. } => occurs_in(alloc, v, &elements),
+        Type::Union { types, .. } => {
+            occurs_in(alloc, v, types.into_iter().collect::<Vec<_>>().as_slice())
+        }
+        _ => false,
+    }
+}